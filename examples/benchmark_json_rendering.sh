@@ -0,0 +1,104 @@
+#!/bin/bash
+
+# CrestRail 读取接口 JSON 生成方式对比
+#
+# 在 100k 行的测试表上比较两种生成方式：
+#   render=database     由 PostgreSQL 的 row_to_json 逐行生成 JSON 文本（默认）
+#   render=application  逐行读取后由服务端的行编码器生成
+#
+# 脚本自行构建 release 版本并在 BENCH_PORT 上启动一个独立的服务端，结束后停止服务端并删除测试表。
+#
+# 用法：DATABASE_URL=postgres://... bash examples/benchmark_json_rendering.sh [轮数]
+
+ROWS="${ROWS:-100000}"
+RUNS="${1:-5}"
+BENCH_PORT="${BENCH_PORT:-3999}"
+BASE_URL="http://localhost:${BENCH_PORT}"
+TABLE="bench_json_rows"
+
+if [ -z "$DATABASE_URL" ]; then
+  echo "请设置 DATABASE_URL"
+  exit 1
+fi
+
+# 颜色定义
+GREEN='\033[0;32m'
+YELLOW='\033[1;33m'
+NC='\033[0m' # No Color
+
+echo "=== CrestRail JSON 生成方式对比（${ROWS} 行，每种方式 ${RUNS} 轮）==="
+echo ""
+
+cd "$(dirname "$0")/.." || exit 1
+
+echo -e "${YELLOW}构建 release 版本${NC}"
+cargo build --release --quiet --bin crestrail 2> /dev/null || { echo "构建失败"; exit 1; }
+echo ""
+
+echo -e "${YELLOW}准备测试表 public.${TABLE}${NC}"
+psql "$DATABASE_URL" -q <<SQL
+SET client_min_messages = warning;
+DROP TABLE IF EXISTS public.${TABLE};
+CREATE TABLE public.${TABLE} (
+    id serial PRIMARY KEY,
+    name text NOT NULL,
+    amount numeric(12, 2) NOT NULL,
+    active boolean NOT NULL,
+    tags text[] NOT NULL,
+    attrs jsonb NOT NULL,
+    created_at timestamptz NOT NULL
+);
+INSERT INTO public.${TABLE} (name, amount, active, tags, attrs, created_at)
+SELECT 'item-' || g,
+       (g % 10000) / 100.0,
+       g % 2 = 0,
+       ARRAY['tag-' || (g % 7), 'tag-' || (g % 11)],
+       jsonb_build_object('size', g % 5, 'color', 'c' || (g % 3)),
+       now() - g * interval '1 minute'
+FROM generate_series(1, ${ROWS}) AS g;
+ANALYZE public.${TABLE};
+SQL
+echo ""
+
+echo -e "${YELLOW}启动服务端（端口 ${BENCH_PORT}）${NC}"
+PORT="$BENCH_PORT" RUST_LOG=warn ./target/release/crestrail > /dev/null 2>&1 &
+SERVER_PID=$!
+trap 'kill "$SERVER_PID" 2>/dev/null' EXIT
+for _ in $(seq 1 50); do
+  curl -s -o /dev/null "$BASE_URL/health" && break
+  sleep 0.2
+done
+echo ""
+
+# 预热结构缓存和连接池
+curl -s -o /dev/null "$BASE_URL/api/public/${TABLE}?limit=1"
+
+run() {
+  local mode="$1"
+  for _ in $(seq 1 "$RUNS"); do
+    curl -s -o /dev/null -w "%{time_total} %{size_download}\n" \
+      -H "Prefer: render=${mode}" \
+      "$BASE_URL/api/public/${TABLE}"
+  done | awk -v mode="$mode" '
+    NR == 1 || $1 < min { min = $1 }
+    $1 > max { max = $1 }
+    { total += $1; size = $2 }
+    END { printf "  %-12s 平均 %6.3f 秒（最快 %.3f，最慢 %.3f），响应体 %d 字节\n", mode, total / NR, min, max, size }'
+}
+
+# 服务端进程至今的峰值内存（Linux），先测 database，其峰值不受 application 影响
+peak_rss() {
+  grep VmHWM "/proc/${SERVER_PID}/status" 2>/dev/null | awk '{ printf "  %-12s 服务端峰值内存 %d MB\n", "", $2 / 1024 }'
+}
+
+echo -e "${YELLOW}GET /api/public/${TABLE}${NC}"
+run database
+peak_rss
+run application
+peak_rss
+echo ""
+
+echo -e "${YELLOW}清理测试表${NC}"
+psql "$DATABASE_URL" -q -c "DROP TABLE IF EXISTS public.${TABLE};"
+
+echo -e "${GREEN}完成${NC}"
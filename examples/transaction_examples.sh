@@ -225,8 +225,78 @@ curl -X POST "$BASE_URL/transaction" \
 echo ""
 echo ""
 
-# 8. 清理：删除所有测试数据
-echo -e "${YELLOW}8. 清理：删除所有测试用户${NC}"
+# 8. Upsert：按邮箱插入或更新（重复执行结果相同）
+echo -e "${YELLOW}8. Upsert：邮箱已存在时更新角色，不存在时插入${NC}"
+curl -X POST "$BASE_URL/transaction" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "operations": [
+      {
+        "method": "UPSERT",
+        "schema": "public",
+        "table": "users",
+        "on_conflict": ["email"],
+        "resolution": "merge-duplicates",
+        "data": [
+          {"username": "txuser1", "email": "txuser1@example.com", "password_hash": "$2b$12$test", "role": "admin"},
+          {"username": "txuser5", "email": "txuser5@example.com", "password_hash": "$2b$12$test", "role": "user"}
+        ]
+      }
+    ]
+  }' | jq '.'
+echo ""
+echo ""
+
+# 9. 引用之前操作的结果：创建用户及其第一篇文章
+echo -e "${YELLOW}9. 引用：用新用户的 id 创建文章（\$ref）${NC}"
+curl -X POST "$BASE_URL/transaction" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "operations": [
+      {
+        "name": "new_user",
+        "method": "POST",
+        "schema": "public",
+        "table": "users",
+        "data": {"username": "txuser6", "email": "txuser6@example.com", "password_hash": "$2b$12$test", "role": "user"}
+      },
+      {
+        "method": "POST",
+        "schema": "public",
+        "table": "posts",
+        "data": {"user_id": {"$ref": "new_user.id"}, "title": "Hello"}
+      }
+    ]
+  }' | jq '.'
+echo ""
+echo ""
+
+# 10. 失败时跳过：重复邮箱的操作单独回滚，其余操作照常提交
+echo -e "${YELLOW}10. on_error=skip：跳过失败的操作${NC}"
+curl -X POST "$BASE_URL/transaction" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "on_error": "skip",
+    "operations": [
+      {
+        "method": "POST",
+        "schema": "public",
+        "table": "users",
+        "data": {"username": "duplicate", "email": "txuser1@example.com", "password_hash": "$2b$12$test", "role": "user"}
+      },
+      {
+        "method": "POST",
+        "schema": "public",
+        "table": "users",
+        "data": {"username": "txuser7", "email": "txuser7@example.com", "password_hash": "$2b$12$test", "role": "user"}
+      }
+    ]
+  }' | jq '. | {success_count, failed_count, operations}'
+echo ""
+echo ""
+
+# 11. 清理：删除所有测试数据
+echo -e "${YELLOW}11. 清理：删除所有测试用户${NC}"
 curl -X POST "$BASE_URL/transaction" \
   -H "Content-Type: application/json" \
   -d '{
@@ -236,6 +306,8 @@ curl -X POST "$BASE_URL/transaction" \
       {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "txuser3"}},
       {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "txuser4"}},
       {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "txuser5"}},
+      {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "txuser6"}},
+      {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "txuser7"}},
       {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "perf1"}},
       {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "perf2"}},
       {"method": "DELETE", "schema": "public", "table": "users", "where": {"username": "perf3"}},
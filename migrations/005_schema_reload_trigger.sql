@@ -0,0 +1,26 @@
+-- ============================================
+-- DDL 变更后自动刷新 CrestRail 结构缓存
+-- ============================================
+-- 事件触发器需要超级用户权限创建；每个需要自动刷新的数据库（主库及租户库）都要执行一次。
+-- 也可以随时手动执行 NOTIFY crestrail_reload_schema; 或调用 POST /api/admin/schema-cache/reload
+
+CREATE OR REPLACE FUNCTION public.crestrail_notify_schema_reload()
+RETURNS event_trigger
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    PERFORM pg_notify('crestrail_reload_schema', '');
+END;
+$$;
+
+-- CREATE / ALTER 等命令结束后
+DROP EVENT TRIGGER IF EXISTS crestrail_schema_reload_ddl;
+CREATE EVENT TRIGGER crestrail_schema_reload_ddl
+    ON ddl_command_end
+    EXECUTE FUNCTION public.crestrail_notify_schema_reload();
+
+-- DROP 命令
+DROP EVENT TRIGGER IF EXISTS crestrail_schema_reload_drop;
+CREATE EVENT TRIGGER crestrail_schema_reload_drop
+    ON sql_drop
+    EXECUTE FUNCTION public.crestrail_notify_schema_reload();
@@ -1,8 +1,10 @@
 use crate::auth::Claims;
 use crate::error::{AppError, Result};
 use crate::tenant_models::*;
+use crate::pool_manager::POOL_MANAGER;
+use crate::schema_cache::{MAIN_DATABASE_ID, SCHEMA_CACHE};
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     Extension, Json,
 };
 use serde::{Deserialize, Serialize};
@@ -425,3 +427,39 @@ pub async fn get_system_stats(
         super_admins,
     }))
 }
+
+// ==================== 结构缓存 ====================
+
+/// 刷新结构缓存请求参数
+#[derive(Debug, Deserialize)]
+pub struct ReloadSchemaQuery {
+    /// 租户数据库 ID，不传时刷新主数据库
+    pub database_id: Option<i32>,
+}
+
+/// POST /api/admin/schema-cache/reload - 手动刷新数据库结构缓存
+pub async fn reload_schema_cache(
+    State(pool): State<PgPool>,
+    Extension(claims): Extension<Claims>,
+    Query(query): Query<ReloadSchemaQuery>,
+) -> Result<Json<serde_json::Value>> {
+    require_admin(&claims)?;
+
+    let database_id = query.database_id.unwrap_or(MAIN_DATABASE_ID);
+    let pool = if database_id == MAIN_DATABASE_ID {
+        pool
+    } else {
+        POOL_MANAGER.get_pool(database_id).ok_or_else(|| {
+            AppError::NotFound(format!("数据库 {} 的连接池尚未创建", database_id))
+        })?
+    };
+
+    let snapshot = SCHEMA_CACHE.reload(&pool, database_id).await?;
+
+    tracing::info!("用户 {} 手动刷新结构缓存: database_id={}", claims.sub, database_id);
+
+    Ok(Json(serde_json::json!({
+        "database_id": database_id,
+        "summary": snapshot.summary(),
+    })))
+}
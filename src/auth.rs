@@ -50,6 +50,11 @@ impl Claims {
     pub fn is_expired(&self) -> bool {
         Utc::now().timestamp() > self.exp
     }
+
+    /// 是否为管理员：超级管理员、租户管理员，或 create_admin 创建的 admin
+    pub fn is_admin(&self) -> bool {
+        matches!(self.role.as_str(), "super_admin" | "tenant_admin" | "admin")
+    }
 }
 
 /// 生成 JWT token
@@ -102,15 +107,23 @@ mod tests {
 
     #[test]
     fn test_generate_and_verify_token() {
-        let token = generate_token("123", "test@example.com", "user").unwrap();
+        let token = generate_token(123, "test@example.com", "user").unwrap();
         let claims = verify_token(&token).unwrap();
 
-        assert_eq!(claims.sub, "123");
+        assert_eq!(claims.sub, 123);
         assert_eq!(claims.email, "test@example.com");
         assert_eq!(claims.role, "user");
         assert!(!claims.is_expired());
     }
 
+    #[test]
+    fn test_is_admin() {
+        for role in ["super_admin", "tenant_admin", "admin"] {
+            assert!(Claims::new(1, "a@example.com".to_string(), role.to_string()).is_admin());
+        }
+        assert!(!Claims::new(1, "a@example.com".to_string(), "user".to_string()).is_admin());
+    }
+
     #[test]
     fn test_hash_and_verify_password() {
         let password = "test_password_123";
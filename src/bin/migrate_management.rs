@@ -1,4 +1,5 @@
 use sqlx::postgres::PgPoolOptions;
+use sqlx::Row;
 use std::fs;
 
 #[tokio::main]
@@ -1,4 +1,5 @@
 use sqlx::postgres::PgPoolOptions;
+use sqlx::Row;
 use std::env;
 
 #[tokio::main]
@@ -26,45 +26,111 @@ pub enum AppError {
     #[error("资源未找到: {0}")]
     NotFound(String),
 
+    #[error("无法提供请求的响应格式: {0}")]
+    NotAcceptable(String),
+
+    #[error("请求过多: {0}")]
+    TooManyRequests(String),
+
     #[error("内部错误: {0}")]
     Internal(String),
+
+    /// 事务中第 index 个操作失败，响应中附带操作序号和 SQLSTATE
+    #[error("操作 {index} 失败: {source}")]
+    Operation {
+        index: usize,
+        source: Box<AppError>,
+    },
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::Database(ref e) => {
+impl AppError {
+    /// 数据库错误的 SQLSTATE（如 23505 唯一约束冲突）
+    pub fn sqlstate(&self) -> Option<String> {
+        match self {
+            AppError::Database(e) => e
+                .as_database_error()
+                .and_then(|e| e.code())
+                .map(|code| code.into_owned()),
+            AppError::Operation { source, .. } => source.sqlstate(),
+            _ => None,
+        }
+    }
+
+    /// 响应状态码和错误信息
+    fn status_and_message(&self) -> (StatusCode, String) {
+        match self {
+            // SQLSTATE 22 类（数据异常，如类型转换失败）属于客户端输入错误
+            AppError::Database(e) if is_data_exception(e) => {
+                (StatusCode::BAD_REQUEST, self.to_string())
+            }
+            // 同一条 upsert 中冲突键重复（ON CONFLICT DO UPDATE 不能两次更新同一行）
+            AppError::Database(_) if self.sqlstate().as_deref() == Some("21000") => {
+                (StatusCode::BAD_REQUEST, self.to_string())
+            }
+            // 序列化失败和死锁：客户端可以重试
+            AppError::Database(_) if matches!(self.sqlstate().as_deref(), Some("40001" | "40P01")) => {
+                (StatusCode::CONFLICT, self.to_string())
+            }
+            AppError::Database(e) => {
                 tracing::error!("数据库错误: {}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
             }
-            AppError::InvalidQuery(ref msg) => {
+            AppError::InvalidQuery(msg) => {
                 (StatusCode::BAD_REQUEST, msg.clone())
             }
-            AppError::InvalidJson(ref e) => {
+            AppError::InvalidJson(e) => {
                 (StatusCode::BAD_REQUEST, format!("JSON 解析错误: {}", e))
             }
-            AppError::Unauthorized(ref msg) => {
+            AppError::Unauthorized(msg) => {
                 (StatusCode::UNAUTHORIZED, msg.clone())
             }
-            AppError::Forbidden(ref msg) => {
+            AppError::Forbidden(msg) => {
                 (StatusCode::FORBIDDEN, msg.clone())
             }
-            AppError::NotFound(ref msg) => {
+            AppError::NotFound(msg) => {
                 (StatusCode::NOT_FOUND, msg.clone())
             }
-            AppError::Internal(ref msg) => {
+            AppError::NotAcceptable(msg) => {
+                (StatusCode::NOT_ACCEPTABLE, msg.clone())
+            }
+            AppError::TooManyRequests(msg) => {
+                (StatusCode::TOO_MANY_REQUESTS, msg.clone())
+            }
+            AppError::Internal(msg) => {
                 tracing::error!("内部错误: {}", msg);
                 (StatusCode::INTERNAL_SERVER_ERROR, msg.clone())
             }
-        };
+            AppError::Operation { index, source } => {
+                let (status, msg) = source.status_and_message();
+                (status, format!("操作 {}: {}", index, msg))
+            }
+        }
+    }
+}
 
-        let body = Json(json!({
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = self.status_and_message();
+
+        let mut body = json!({
             "error": error_message,
-        }));
+        });
+        if let AppError::Operation { index, .. } = &self {
+            body["operation_index"] = json!(index);
+            body["sqlstate"] = json!(self.sqlstate());
+        }
 
-        (status, body).into_response()
+        (status, Json(body)).into_response()
     }
 }
 
+/// 是否为数据异常（SQLSTATE 22xxx）
+fn is_data_exception(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code.starts_with("22"))
+}
+
 pub type Result<T> = std::result::Result<T, AppError>;
 
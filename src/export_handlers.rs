@@ -1,12 +1,17 @@
 use crate::error::Result;
+use crate::handlers::render_in_database;
+use crate::prefer::Preferences;
 use crate::query_builder::{QueryParams, SqlBuilder};
+use crate::row_encoder::{column_to_string, rows_to_json};
+use crate::schema_cache::{MAIN_DATABASE_ID, SCHEMA_CACHE};
+use crate::schema_handlers::resolve_embeds;
+use crate::stream::{stream_rows, StreamFormat};
 use axum::{
     extract::{Path, Query, State},
     http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::Value;
 use sqlx::{Column, PgPool, Row};
 use std::collections::HashMap;
 
@@ -17,7 +22,12 @@ pub async fn export_csv(
     Query(query): Query<HashMap<String, String>>,
 ) -> Result<Response> {
     // 解析查询参数
-    let params = QueryParams::from_query_map(query)?;
+    let mut params = QueryParams::from_query_map(query)?;
+    let catalog = SCHEMA_CACHE
+        .for_table(&pool, MAIN_DATABASE_ID, &schema, &table)
+        .await?;
+    params.column_types = catalog.column_types(&schema, &table).unwrap_or_default();
+    resolve_embeds(&catalog, &schema, &table, &mut params)?;
 
     // 构建 SQL
     let builder = SqlBuilder::new(schema.clone(), table.clone(), params)?;
@@ -53,7 +63,7 @@ pub async fn export_csv(
     for row in &rows {
         let mut values = Vec::new();
         for (i, _column) in row.columns().iter().enumerate() {
-            let value = get_value_as_string(&row, i);
+            let value = column_to_string(row, i);
             values.push(escape_csv_field(&value));
         }
         csv.push_str(&values.join(","));
@@ -80,62 +90,40 @@ pub async fn export_json(
     State(pool): State<PgPool>,
     Path((schema, table)): Path<(String, String)>,
     Query(query): Query<HashMap<String, String>>,
+    request_headers: HeaderMap,
 ) -> Result<Response> {
+    let prefs = Preferences::from_headers(&request_headers)?;
+
     // 解析查询参数
-    let params = QueryParams::from_query_map(query)?;
+    let mut params = QueryParams::from_query_map(query)?;
+    let catalog = SCHEMA_CACHE
+        .for_table(&pool, MAIN_DATABASE_ID, &schema, &table)
+        .await?;
+    params.column_types = catalog.column_types(&schema, &table).unwrap_or_default();
+    resolve_embeds(&catalog, &schema, &table, &mut params)?;
 
     // 构建 SQL
     let builder = SqlBuilder::new(schema.clone(), table.clone(), params)?;
-    let (sql, args) = builder.build_select()?;
 
-    tracing::debug!("导出 JSON - 执行 SQL: {}", sql);
+    // 默认由 PostgreSQL 直接生成 JSON 并逐块写出，Prefer: render=application 时逐行编码；两种方式都输出紧凑格式
+    let mut response = if render_in_database(&prefs) {
+        let (sql, args) = builder.build_select_json_rows()?;
 
-    // 执行查询
-    let rows = sqlx::query_with(&sql, args).fetch_all(&pool).await?;
+        tracing::debug!("导出 JSON - 执行 SQL: {}", sql);
 
-    // 转换为 JSON
-    let results: Vec<Value> = rows
-        .iter()
-        .map(|row| {
-            let mut obj = serde_json::Map::new();
-            for column in row.columns() {
-                let key = column.name().to_string();
-                let idx = column.ordinal();
-
-                // 尝试不同的类型
-                let value: Value = if let Ok(v) = row.try_get::<String, _>(idx) {
-                    Value::String(v)
-                } else if let Ok(v) = row.try_get::<i32, _>(idx) {
-                    serde_json::json!(v)
-                } else if let Ok(v) = row.try_get::<i64, _>(idx) {
-                    serde_json::json!(v)
-                } else if let Ok(v) = row.try_get::<f64, _>(idx) {
-                    serde_json::json!(v)
-                } else if let Ok(v) = row.try_get::<bool, _>(idx) {
-                    Value::Bool(v)
-                } else if let Ok(v) = row.try_get::<Option<String>, _>(idx) {
-                    v.map(Value::String).unwrap_or(Value::Null)
-                } else if let Ok(v) = row.try_get::<Option<i32>, _>(idx) {
-                    v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-                } else if let Ok(v) = row.try_get::<Option<i64>, _>(idx) {
-                    v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-                } else if let Ok(v) = row.try_get::<serde_json::Value, _>(idx) {
-                    v
-                } else {
-                    Value::Null
-                };
-
-                obj.insert(key, value);
-            }
-            Value::Object(obj)
-        })
-        .collect();
+        stream_rows(pool, sql, args, StreamFormat::JsonArray, true).await?
+    } else {
+        let (sql, args) = builder.build_select()?;
 
-    let json_str = serde_json::to_string_pretty(&results)?;
+        tracing::debug!("导出 JSON - 执行 SQL: {}", sql);
+
+        let rows = sqlx::query_with(&sql, args).fetch_all(&pool).await?;
+        serde_json::to_string(&rows_to_json(&rows))?.into_response()
+    };
 
     // 设置响应头
     let filename = format!("{}_{}.json", schema, table);
-    let mut headers = HeaderMap::new();
+    let headers = response.headers_mut();
     headers.insert(
         header::CONTENT_TYPE,
         HeaderValue::from_static("application/json; charset=utf-8"),
@@ -145,7 +133,7 @@ pub async fn export_json(
         HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename)).unwrap(),
     );
 
-    Ok((StatusCode::OK, headers, json_str).into_response())
+    Ok(response)
 }
 
 /// POST /api/export/sql - 导出 SQL 查询结果为 CSV
@@ -196,7 +184,7 @@ pub async fn export_sql_csv(
     for row in &rows {
         let mut values = Vec::new();
         for (i, _column) in row.columns().iter().enumerate() {
-            let value = get_value_as_string(&row, i);
+            let value = column_to_string(row, i);
             values.push(escape_csv_field(&value));
         }
         csv.push_str(&values.join(","));
@@ -225,29 +213,3 @@ fn escape_csv_field(field: &str) -> String {
         field.to_string()
     }
 }
-
-/// 从行中获取值作为字符串
-fn get_value_as_string(row: &sqlx::postgres::PgRow, idx: usize) -> String {
-    if let Ok(v) = row.try_get::<String, _>(idx) {
-        v
-    } else if let Ok(v) = row.try_get::<i32, _>(idx) {
-        v.to_string()
-    } else if let Ok(v) = row.try_get::<i64, _>(idx) {
-        v.to_string()
-    } else if let Ok(v) = row.try_get::<f64, _>(idx) {
-        v.to_string()
-    } else if let Ok(v) = row.try_get::<bool, _>(idx) {
-        v.to_string()
-    } else if let Ok(v) = row.try_get::<Option<String>, _>(idx) {
-        v.unwrap_or_else(|| "NULL".to_string())
-    } else if let Ok(v) = row.try_get::<Option<i32>, _>(idx) {
-        v.map(|n| n.to_string()).unwrap_or_else(|| "NULL".to_string())
-    } else if let Ok(v) = row.try_get::<Option<i64>, _>(idx) {
-        v.map(|n| n.to_string()).unwrap_or_else(|| "NULL".to_string())
-    } else if let Ok(v) = row.try_get::<serde_json::Value, _>(idx) {
-        v.to_string()
-    } else {
-        "NULL".to_string()
-    }
-}
-
@@ -1,126 +1,499 @@
-use crate::error::Result;
-use crate::query_builder::{QueryParams, SqlBuilder};
+use crate::auth::Claims;
+use crate::error::{AppError, Result};
+use crate::prefer::{CountMode, Preferences, RenderMode, Resolution, ReturnMode};
+use crate::row_encoder::row_to_json;
+use crate::query_builder::{Cursor, QueryParams, SelectItem, SqlBuilder, CURSOR_COLUMN_PREFIX};
+use crate::schema_cache::{DatabaseSchema, TableInfo, MAIN_DATABASE_ID, SCHEMA_CACHE};
+use crate::schema_handlers::{prepare_cursor, prepare_upsert, resolve_embeds};
+use crate::session::DbConnection;
+use crate::stream::{apply_row_cap, stream_page, stream_rows, StreamFormat, STREAM_ROW_CAPS};
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    extract::{Extension, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use serde_json::Value;
-use sqlx::{PgPool, Row, Column};
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use sqlx::postgres::PgArguments;
+use sqlx::{Connection, PgConnection, PgPool};
 use std::collections::HashMap;
+use std::sync::Arc;
 
-/// 将数据库行转换为 JSON 值（智能类型处理）
-fn row_to_json(row: &sqlx::postgres::PgRow) -> Value {
-    let mut obj = serde_json::Map::new();
-    for column in row.columns() {
-        let key = column.name().to_string();
-        let idx = column.ordinal();
-        
-        // 尝试不同的类型
-        let value: Value = if let Ok(v) = row.try_get::<String, _>(idx) {
-            Value::String(v)
-        } else if let Ok(v) = row.try_get::<i32, _>(idx) {
-            serde_json::json!(v)
-        } else if let Ok(v) = row.try_get::<i64, _>(idx) {
-            serde_json::json!(v)
-        } else if let Ok(v) = row.try_get::<f64, _>(idx) {
-            serde_json::json!(v)
-        } else if let Ok(v) = row.try_get::<bool, _>(idx) {
-            Value::Bool(v)
-        } else if let Ok(v) = row.try_get::<Option<String>, _>(idx) {
-            v.map(Value::String).unwrap_or(Value::Null)
-        } else if let Ok(v) = row.try_get::<Option<i32>, _>(idx) {
-            v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-        } else if let Ok(v) = row.try_get::<Option<i64>, _>(idx) {
-            v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-        } else if let Ok(v) = row.try_get::<serde_json::Value, _>(idx) {
-            v
-        } else {
-            Value::Null
-        };
-        
-        obj.insert(key, value);
-    }
-    Value::Object(obj)
-}
+/// 单次 PATCH / DELETE 允许影响的最大行数（MAX_AFFECTED_ROWS，未设置时不限制）
+static MAX_AFFECTED_ROWS: Lazy<Option<i64>> = Lazy::new(|| {
+    std::env::var("MAX_AFFECTED_ROWS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|limit| *limit > 0)
+});
+
+/// 读取接口是否默认由 PostgreSQL 直接生成 JSON（默认开启，SERVER_SIDE_JSON=false 时改为逐行编码）
+///
+/// 按请求可用 Prefer: render=application 改为逐行编码
+static SERVER_SIDE_JSON: Lazy<bool> = Lazy::new(|| {
+    std::env::var("SERVER_SIDE_JSON")
+        .map(|v| !matches!(v.to_lowercase().as_str(), "false" | "0" | "off"))
+        .unwrap_or(true)
+});
+
+/// 试运行时返回的示例行数
+const DRY_RUN_SAMPLE_ROWS: usize = 10;
+
+/// 单对象响应的媒体类型：Accept 中包含时返回对象而不是数组
+const OBJECT_MEDIA_TYPE: &str = "application/vnd.crestrail.object+json";
 
 /// GET /api/:schema/:table - 查询数据
 pub async fn get_records(
     State(pool): State<PgPool>,
+    mut db: DbConnection,
     Path((schema, table)): Path<(String, String)>,
     Query(query): Query<HashMap<String, String>>,
-) -> Result<Json<Value>> {
+    headers: HeaderMap,
+) -> Result<Response> {
     tracing::debug!("GET /api/{}/{} - 查询参数: {:?}", schema, table, query);
 
+    let prefs = Preferences::from_headers(&headers)?;
+    let singular = wants_object(&headers);
+    // 单对象响应只有一行，不需要流式输出
+    let stream_format = if singular {
+        None
+    } else {
+        StreamFormat::from_request(&headers, prefs.stream)
+    };
+
     // 解析查询参数
-    let params = QueryParams::from_query_map(query)?;
+    let mut params = QueryParams::from_query_map(query)?;
 
-    // 构建 SQL
-    let builder = SqlBuilder::new(schema, table, params)?;
-    let (sql, args) = builder.build_select()?;
+    // Range: 0-24 作为 limit/offset 的替代写法（查询参数优先）
+    if let Some((offset, limit)) = parse_range(&headers)? {
+        params.offset = params.offset.or(Some(offset));
+        params.limit = params.limit.or(limit);
+    }
+    let offset = params.offset.unwrap_or(0);
+
+    // 从结构缓存加载列类型，用于按类型绑定参数
+    let catalog = SCHEMA_CACHE
+        .for_table(&pool, MAIN_DATABASE_ID, &schema, &table)
+        .await?;
+    params.column_types = catalog.column_types(&schema, &table).unwrap_or_default();
+
+    // 解析嵌入资源的外键关联
+    resolve_embeds(&catalog, &schema, &table, &mut params)?;
+
+    // 游标分页：补全主键排序键
+    prepare_cursor(&catalog, &schema, &table, &mut params)?;
+    let keyset = params
+        .cursor
+        .as_ref()
+        .map(|_| (params.order_by.len(), params.limit));
+
+    // 流式响应按路由应用行数上限
+    let row_cap = match stream_format {
+        Some(_) if keyset.is_some() => {
+            return Err(AppError::InvalidQuery(
+                "游标分页不支持流式响应".to_string(),
+            ))
+        }
+        Some(_) if db.is_pinned() => {
+            return Err(AppError::InvalidQuery(
+                "交互式事务中不支持流式响应".to_string(),
+            ))
+        }
+        Some(_) => apply_row_cap(&mut params, STREAM_ROW_CAPS.for_route(&schema, &table))?,
+        None => None,
+    };
+
+    let builder = SqlBuilder::new(schema.clone(), table.clone(), params)?;
+
+    // 流式响应：逐行写入响应体，不统计总行数
+    if let Some(format) = stream_format {
+        // 开始输出后无法再返回错误，超出上限的请求在输出前拒绝，而不是截断响应体
+        if let Some(cap) = row_cap {
+            let (sql, args) = builder.build_exceeds(cap)?;
+            tracing::debug!("执行 SQL: {}", sql);
+            let exceeds: bool = sqlx::query_scalar_with(&sql, args).fetch_one(&pool).await?;
+            if exceeds {
+                return Err(AppError::InvalidQuery(format!(
+                    "结果超过流式响应的上限 {} 行，请使用 limit 或分页",
+                    cap
+                )));
+            }
+        }
+
+        let in_database = render_in_database(&prefs);
+        let (sql, args) = if in_database {
+            builder.build_select_json_rows()?
+        } else {
+            builder.build_select()?
+        };
+        tracing::debug!("执行 SQL（流式）: {}", sql);
+        return stream_rows(pool, sql, args, format, in_database).await;
+    }
+
+    // 游标分页需要在服务端处理排序键列，只能逐行编码
+    let in_database = keyset.is_none() && render_in_database(&prefs);
+
+    // 由数据库生成的 JSON 文本经流式响应的通道逐块写出，不在内存中组装整个响应体
+    if in_database && !singular {
+        let total = match prefs.count {
+            Some(mode) => {
+                let mut conn = db.begin_read().await?;
+                let total = count_rows(&mut conn, &builder, &schema, &table, mode).await?;
+                conn.finish().await?;
+                Some(total)
+            }
+            None => None,
+        };
+        let (sql, args) = builder.build_select_json_page()?;
+        tracing::debug!("执行 SQL: {}", sql);
+        let (row_count, body) = stream_page(db, sql, args).await?;
+        return list_response(offset, row_count, total, singular, body);
+    }
 
+    let mut conn = db.begin_read().await?;
+
+    let (row_count, body) = if in_database {
+        (1, render_object_in_database(&mut conn, &builder).await?)
+    } else {
+        let (sql, args) = builder.build_select()?;
+        tracing::debug!("执行 SQL: {}", sql);
+
+        let rows = sqlx::query_with(&sql, args).fetch_all(&mut *conn).await?;
+        let mut results: Vec<Value> = rows.iter().map(row_to_json).collect();
+
+        let next_cursor = match keyset {
+            Some((key_len, limit)) => take_next_cursor(&mut results, key_len, limit)?,
+            None => None,
+        };
+
+        // 单对象响应要求结果恰好一行
+        if singular {
+            expect_single(results.len() as u64)?;
+        }
+
+        let row_count = results.len();
+        // 游标分页时同时返回下一页游标
+        let body = if singular {
+            results.into_iter().next().unwrap_or(Value::Null)
+        } else if keyset.is_some() {
+            serde_json::json!({
+                "data": results,
+                "next_cursor": next_cursor,
+            })
+        } else {
+            Value::Array(results)
+        };
+        (row_count, serde_json::to_string(&body)?)
+    };
+
+    // 统计总行数
+    let total = match prefs.count {
+        Some(mode) => Some(count_rows(&mut conn, &builder, &schema, &table, mode).await?),
+        None => None,
+    };
+    conn.finish().await?;
+
+    list_response(offset, row_count, total, singular, body)
+}
+
+/// 组装读取接口的响应：Content-Range、状态码（未返回全部匹配行时为 206）和媒体类型
+fn list_response(
+    offset: i64,
+    row_count: usize,
+    total: Option<i64>,
+    singular: bool,
+    body: impl IntoResponse,
+) -> Result<Response> {
+    let content_range = format_content_range(offset, row_count, total);
+    let partial = total.is_some_and(|total| offset + (row_count as i64) < total);
+    let status = if partial {
+        StatusCode::PARTIAL_CONTENT
+    } else {
+        StatusCode::OK
+    };
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CONTENT_RANGE,
+        HeaderValue::from_str(&content_range)
+            .map_err(|e| AppError::Internal(format!("无效的 Content-Range: {}", e)))?,
+    );
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(if singular {
+            OBJECT_MEDIA_TYPE
+        } else {
+            "application/json"
+        }),
+    );
+
+    Ok((status, response_headers, body).into_response())
+}
+
+/// 本次请求是否由 PostgreSQL 直接生成 JSON 响应体
+pub(crate) fn render_in_database(prefs: &Preferences) -> bool {
+    match prefs.render {
+        Some(mode) => mode == RenderMode::Database,
+        None => *SERVER_SIDE_JSON,
+    }
+}
+
+/// 由 PostgreSQL 生成单对象响应的 JSON 文本，结果必须恰好一行
+async fn render_object_in_database(conn: &mut PgConnection, builder: &SqlBuilder) -> Result<String> {
+    let (sql, args) = builder.build_select_json_page()?;
     tracing::debug!("执行 SQL: {}", sql);
 
-    // 执行查询
-    let rows = sqlx::query_with(&sql, args)
-        .fetch_all(&pool)
-        .await?;
+    // 第二列是结果的总行数，只需读取第一行
+    let row: Option<(String, i64)> = sqlx::query_as_with(&sql, args).fetch_optional(conn).await?;
+    let (object, rows) = row.unwrap_or_default();
+    expect_single(rows as u64)?;
+    Ok(object)
+}
 
-    // 转换为 JSON
-    let results: Vec<Value> = rows.iter().map(row_to_json).collect();
+/// 移除结果中附带的排序键列，并根据最后一行生成下一页游标（已到末页时为 None）
+fn take_next_cursor(
+    results: &mut [Value],
+    key_len: usize,
+    limit: Option<i64>,
+) -> Result<Option<String>> {
+    let keys: Vec<String> = (0..key_len)
+        .map(|i| format!("{}{}", CURSOR_COLUMN_PREFIX, i))
+        .collect();
 
-    Ok(Json(Value::Array(results)))
+    let mut last_values = Vec::new();
+    for row in results.iter_mut() {
+        if let Some(obj) = row.as_object_mut() {
+            last_values = keys.iter().map(|key| obj.remove(key)).collect();
+        }
+    }
+
+    let full_page = limit.is_some_and(|limit| results.len() as i64 >= limit);
+    if !full_page || results.is_empty() {
+        return Ok(None);
+    }
+
+    let values = last_values
+        .into_iter()
+        .map(|value| match value {
+            Some(Value::String(v)) => Ok(v),
+            _ => Err(AppError::InvalidQuery(
+                "游标分页的排序列不能包含 NULL 值".to_string(),
+            )),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(Cursor::encode(&values)))
+}
+
+/// 按 Prefer: count=... 统计匹配的总行数
+async fn count_rows(
+    conn: &mut PgConnection,
+    builder: &SqlBuilder,
+    schema: &str,
+    table: &str,
+    mode: CountMode,
+) -> Result<i64> {
+    match mode {
+        CountMode::Exact => {
+            let (sql, args) = builder.build_count()?;
+            tracing::debug!("执行 SQL: {}", sql);
+            Ok(sqlx::query_scalar_with(&sql, args).fetch_one(conn).await?)
+        }
+        CountMode::Planned => planned_count(conn, builder).await,
+        CountMode::Estimated => {
+            // reltuples 是表级估算值，不考虑过滤条件；表从未 ANALYZE 时为 -1
+            let reltuples: Option<f32> = sqlx::query_scalar(
+                r#"
+                SELECT c.reltuples
+                FROM pg_class c
+                JOIN pg_namespace n ON n.oid = c.relnamespace
+                WHERE n.nspname = $1 AND c.relname = $2
+                "#,
+            )
+            .bind(schema)
+            .bind(table)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+            match reltuples {
+                Some(n) if n >= 0.0 => Ok(n as i64),
+                _ => planned_count(conn, builder).await,
+            }
+        }
+    }
+}
+
+/// 从 EXPLAIN 的执行计划中读取估算行数
+async fn planned_count(conn: &mut PgConnection, builder: &SqlBuilder) -> Result<i64> {
+    let (sql, args) = builder.build_count_plan()?;
+    tracing::debug!("执行 SQL: {}", sql);
+
+    let plan: Value = sqlx::query_scalar_with(&sql, args).fetch_one(conn).await?;
+
+    plan.pointer("/0/Plan/Plan Rows")
+        .and_then(Value::as_f64)
+        .map(|rows| rows as i64)
+        .ok_or_else(|| AppError::Internal("无法从执行计划中读取估算行数".to_string()))
+}
+
+/// 解析 Range 请求头: `0-24`、`items=0-24` 或 `25-`，返回 (offset, limit)
+fn parse_range(headers: &HeaderMap) -> Result<Option<(i64, Option<i64>)>> {
+    let value = match headers.get(header::RANGE) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let invalid = || AppError::InvalidQuery("无效的 Range 请求头，格式应为 0-24".to_string());
+
+    let value = value.to_str().map_err(|_| invalid())?.trim();
+    let value = value.strip_prefix("items=").unwrap_or(value);
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+
+    let start: i64 = start.trim().parse().map_err(|_| invalid())?;
+    let end = end.trim();
+
+    if end.is_empty() {
+        return Ok(Some((start, None)));
+    }
+
+    let end: i64 = end.parse().map_err(|_| invalid())?;
+    if start < 0 || end < start {
+        return Err(invalid());
+    }
+
+    Ok(Some((start, Some(end - start + 1))))
+}
+
+/// 生成 Content-Range 响应头: `0-24/3512`，无数据时为 `*/3512`，总数未知时为 `0-24/*`
+fn format_content_range(offset: i64, returned: usize, total: Option<i64>) -> String {
+    let range = if returned == 0 {
+        "*".to_string()
+    } else {
+        format!("{}-{}", offset, offset + returned as i64 - 1)
+    };
+
+    let total = total.map_or_else(|| "*".to_string(), |total| total.to_string());
+
+    format!("{}/{}", range, total)
 }
 
 /// POST /api/:schema/:table - 插入数据
 pub async fn create_record(
     State(pool): State<PgPool>,
+    mut db: DbConnection,
     Path((schema, table)): Path<(String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     Json(data): Json<Value>,
-) -> Result<(StatusCode, Json<Value>)> {
+) -> Result<Response> {
     tracing::debug!("POST /api/{}/{} - 数据: {:?}", schema, table, data);
 
-    // 处理批量插入或单条插入
-    let records = if data.is_array() {
-        data.as_array().unwrap().clone()
-    } else {
-        vec![data]
-    };
-
-    let mut results = Vec::new();
+    let prefs = Preferences::from_headers(&headers)?;
 
-    for record in records {
-        // 构建 SQL
-        let builder = SqlBuilder::new(schema.clone(), table.clone(), QueryParams::default())?;
-        let (sql, args) = builder.build_insert(&record)?;
+    // 解析查询参数（columns 指定批量插入的列集合，on_conflict 指定 upsert 冲突列）
+    let mut params = QueryParams::from_query_map(query)?;
+    let catalog = load_mutation_catalog(&pool, &schema, &table, &mut params).await?;
 
-        tracing::debug!("执行 SQL: {}", sql);
+    // 指定了 on_conflict 但没有 Prefer: resolution 时按合并处理
+    let resolution = prefs.resolution.or_else(|| {
+        params
+            .on_conflict
+            .as_ref()
+            .map(|_| Resolution::MergeDuplicates)
+    });
+    if resolution.is_some() {
+        prepare_upsert(&catalog, &schema, &table, &mut params)?;
+    }
 
-        // 执行插入
-        let row = sqlx::query_with(&sql, args)
-            .fetch_one(&pool)
-            .await?;
+    // headers-only 只需要主键用于生成 Location
+    let primary_key = catalog
+        .table(&schema, &table)
+        .map(|info| info.primary_key.clone())
+        .unwrap_or_default();
+    let returning = match prefs.returning {
+        ReturnMode::Representation => true,
+        ReturnMode::HeadersOnly if !primary_key.is_empty() => {
+            params.select = Some(primary_key.iter().cloned().map(SelectItem::Column).collect());
+            true
+        }
+        _ => false,
+    };
 
-        // 转换为 JSON
-        results.push(row_to_json(&row));
+    // 构建 SQL：数组数据生成多行 INSERT，过大时拆分为多条
+    let mut builder = SqlBuilder::new(schema.clone(), table.clone(), params)?;
+    if !returning {
+        builder = builder.without_returning();
     }
-
-    let response = if results.len() == 1 {
-        results.into_iter().next().unwrap()
-    } else {
-        Value::Array(results)
+    let statements = match resolution {
+        Some(resolution) => builder.build_upsert(&data, resolution)?,
+        None => builder.build_insert(&data)?,
     };
 
-    Ok((StatusCode::CREATED, Json(response)))
+    // 所有语句在同一事务中执行，任意一行失败时整批回滚
+    let mut tx = db.acquire().await?.begin().await?;
+    let mut results = Vec::new();
+    for (sql, args) in statements {
+        tracing::debug!("执行 SQL: {}", sql);
+        let query = sqlx::query_with(&sql, args);
+        if returning {
+            results.extend(query.fetch_all(&mut *tx).await?.iter().map(row_to_json));
+        } else {
+            query.execute(&mut *tx).await?;
+        }
+    }
+    tx.commit().await?;
+
+    match prefs.returning {
+        ReturnMode::Representation => {
+            // 单个对象返回对象，数组返回数组
+            let body = if data.is_array() {
+                Value::Array(results)
+            } else {
+                results.into_iter().next().unwrap_or(Value::Null)
+            };
+            Ok((StatusCode::CREATED, Json(body)).into_response())
+        }
+        ReturnMode::HeadersOnly => {
+            // 只插入了一行时返回其按主键访问的地址
+            let mut response_headers = HeaderMap::new();
+            if let [row] = results.as_slice() {
+                // 各主键值分别编码，值中的逗号不会与复合主键的分隔符混淆
+                let id = primary_key
+                    .iter()
+                    .map(|column| {
+                        encode_path_segment(&row.get(column).map(json_key_text).unwrap_or_default())
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let location = format!(
+                    "/api/{}/{}/{}",
+                    encode_path_segment(&schema),
+                    encode_path_segment(&table),
+                    id
+                );
+                response_headers.insert(
+                    header::LOCATION,
+                    HeaderValue::from_str(&location)
+                        .map_err(|e| AppError::Internal(format!("无效的 Location: {}", e)))?,
+                );
+            }
+            Ok((StatusCode::CREATED, response_headers).into_response())
+        }
+        ReturnMode::Minimal => Ok(StatusCode::CREATED.into_response()),
+    }
 }
 
 /// PATCH /api/:schema/:table - 更新数据
 pub async fn update_records(
     State(pool): State<PgPool>,
+    mut db: DbConnection,
     Path((schema, table)): Path<(String, String)>,
     Query(query): Query<HashMap<String, String>>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
     Json(data): Json<Value>,
-) -> Result<Json<Value>> {
+) -> Result<Response> {
     tracing::debug!(
         "PATCH /api/{}/{} - 查询参数: {:?}, 数据: {:?}",
         schema,
@@ -129,51 +502,537 @@ pub async fn update_records(
         data
     );
 
-    // 解析查询参数
-    let params = QueryParams::from_query_map(query)?;
+    let prefs = Preferences::from_headers(&headers)?;
+    let singular = wants_object(&headers);
 
-    // 构建 SQL
-    let builder = SqlBuilder::new(schema, table, params)?;
-    let (sql, args) = builder.build_update(&data)?;
+    // 解析查询参数
+    let mut params = QueryParams::from_mutation_query(query)?;
 
-    tracing::debug!("执行 SQL: {}", sql);
+    // 不带过滤条件的 UPDATE 会改写整张表，只有管理员显式声明 allow-full-table 时才执行
+    if params.filters.is_empty() {
+        if !prefs.allow_full_table {
+            return Err(AppError::InvalidQuery(
+                "UPDATE 操作必须提供过滤条件；如需更新整张表，请以管理员身份发送 Prefer: allow-full-table"
+                    .to_string(),
+            ));
+        }
+        if !claims.is_some_and(|Extension(claims)| claims.is_admin()) {
+            return Err(AppError::Forbidden("只有管理员可以更新整张表".to_string()));
+        }
+    }
 
-    // 执行更新
-    let rows = sqlx::query_with(&sql, args)
-        .fetch_all(&pool)
-        .await?;
+    load_mutation_catalog(&pool, &schema, &table, &mut params).await?;
 
-    // 转换为 JSON
-    let results: Vec<Value> = rows.iter().map(row_to_json).collect();
+    // 构建 SQL
+    let builder = mutation_builder(schema, table, params, &prefs)?;
+    let (sql, args) = builder.build_update(&data)?;
 
-    Ok(Json(Value::Array(results)))
+    let result = execute_mutation(db.acquire().await?, &sql, args, &prefs, singular).await?;
+    Ok(mutation_response(result, &prefs, singular))
 }
 
 /// DELETE /api/:schema/:table - 删除数据
 pub async fn delete_records(
     State(pool): State<PgPool>,
+    mut db: DbConnection,
     Path((schema, table)): Path<(String, String)>,
     Query(query): Query<HashMap<String, String>>,
-) -> Result<(StatusCode, Json<Value>)> {
+    headers: HeaderMap,
+) -> Result<Response> {
     tracing::debug!("DELETE /api/{}/{} - 查询参数: {:?}", schema, table, query);
 
+    let prefs = Preferences::from_headers(&headers)?;
+    let singular = wants_object(&headers);
+
     // 解析查询参数
-    let params = QueryParams::from_query_map(query)?;
+    let mut params = QueryParams::from_mutation_query(query)?;
+    load_mutation_catalog(&pool, &schema, &table, &mut params).await?;
 
     // 构建 SQL
-    let builder = SqlBuilder::new(schema, table, params)?;
+    let builder = mutation_builder(schema, table, params, &prefs)?;
     let (sql, args) = builder.build_delete()?;
 
+    let result = execute_mutation(db.acquire().await?, &sql, args, &prefs, singular).await?;
+    Ok(mutation_response(result, &prefs, singular))
+}
+
+/// GET /api/:schema/:table/:id - 按主键查询单条数据
+pub async fn get_record(
+    State(pool): State<PgPool>,
+    mut db: DbConnection,
+    Path((schema, table, id)): Path<(String, String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Json<Value>> {
+    tracing::debug!("GET /api/{}/{}/{} - 查询参数: {:?}", schema, table, id, query);
+
+    // 解析查询参数（select、嵌入资源等），并按主键追加过滤条件
+    let mut params = QueryParams::from_query_map(query)?;
+    let catalog = SCHEMA_CACHE
+        .for_table(&pool, MAIN_DATABASE_ID, &schema, &table)
+        .await?;
+    params.column_types = catalog.column_types(&schema, &table).unwrap_or_default();
+    params.filter_by_key(&keyed_table(&catalog, &schema, &table)?.primary_key, &id)?;
+
+    // 解析嵌入资源的外键关联
+    resolve_embeds(&catalog, &schema, &table, &mut params)?;
+
+    // 构建 SQL
+    let builder = SqlBuilder::new(schema.clone(), table.clone(), params)?;
+    let (sql, args) = builder.build_select()?;
+
     tracing::debug!("执行 SQL: {}", sql);
 
-    // 执行删除
-    let rows = sqlx::query_with(&sql, args)
-        .fetch_all(&pool)
+    let mut conn = db.begin_read().await?;
+    let rows = sqlx::query_with(&sql, args).fetch_all(&mut *conn).await?;
+    conn.finish().await?;
+    let row = rows
+        .first()
+        .ok_or_else(|| record_not_found(&schema, &table, &id))?;
+
+    Ok(Json(row_to_json(row)))
+}
+
+/// PUT /api/:schema/:table/:id - 按主键整行替换，记录不存在时插入
+///
+/// 请求体中未出现的列恢复为列默认值；请求体中的主键值必须与路径一致
+pub async fn replace_record(
+    State(pool): State<PgPool>,
+    mut db: DbConnection,
+    Path((schema, table, id)): Path<(String, String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    Json(data): Json<Value>,
+) -> Result<Response> {
+    tracing::debug!("PUT /api/{}/{}/{} - 数据: {:?}", schema, table, id, data);
+
+    let prefs = Preferences::from_headers(&headers)?;
+
+    let mut params = QueryParams::from_mutation_query(query)?;
+    let catalog = load_mutation_catalog(&pool, &schema, &table, &mut params).await?;
+    let info = keyed_table(&catalog, &schema, &table)?;
+
+    let mut row = match data {
+        Value::Object(row) => row,
+        _ => return Err(AppError::InvalidQuery("期望 JSON 对象".to_string())),
+    };
+
+    // 主键取自路径，请求体中出现时必须一致
+    for (column, value) in QueryParams::split_key(&info.primary_key, &id)? {
+        match row.get(column) {
+            Some(body_value) if json_key_text(body_value) != value => {
+                return Err(AppError::InvalidQuery(format!(
+                    "请求体中的主键 {} 与路径不一致",
+                    column
+                )));
+            }
+            Some(_) => {}
+            None => {
+                row.insert(column.clone(), Value::String(value.to_string()));
+            }
+        }
+    }
+
+    // 以全部可写列插入（缺失的列为 DEFAULT），主键冲突时用插入值覆盖整行
+    let (columns, overriding) = replace_columns(info, &row)?;
+    params.columns = Some(columns);
+    params.on_conflict = Some(info.primary_key.clone());
+
+    let mut builder = mutation_builder(schema.clone(), table.clone(), params, &prefs)?;
+    if overriding {
+        builder = builder.overriding_system_value();
+    }
+    let statements = builder.build_upsert(&Value::Object(row), Resolution::MergeDuplicates)?;
+    let (sql, args) = statements
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::Internal("PUT 未生成 SQL".to_string()))?;
+
+    let result = execute_mutation(db.acquire().await?, &sql, args, &prefs, false).await?;
+    single_response(result, &prefs, &schema, &table, &id)
+}
+
+/// PATCH /api/:schema/:table/:id - 按主键更新单条数据
+pub async fn update_record(
+    State(pool): State<PgPool>,
+    mut db: DbConnection,
+    Path((schema, table, id)): Path<(String, String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    Json(data): Json<Value>,
+) -> Result<Response> {
+    tracing::debug!("PATCH /api/{}/{}/{} - 数据: {:?}", schema, table, id, data);
+
+    // 按主键定位，最多影响一行
+    let mut prefs = Preferences::from_headers(&headers)?;
+    prefs.max_affected = Some(1);
+
+    let mut params = QueryParams::from_mutation_query(query)?;
+    let catalog = load_mutation_catalog(&pool, &schema, &table, &mut params).await?;
+    params.filter_by_key(&keyed_table(&catalog, &schema, &table)?.primary_key, &id)?;
+
+    let builder = mutation_builder(schema.clone(), table.clone(), params, &prefs)?;
+    let (sql, args) = builder.build_update(&data)?;
+
+    let result = execute_mutation(db.acquire().await?, &sql, args, &prefs, false).await?;
+    single_response(result, &prefs, &schema, &table, &id)
+}
+
+/// DELETE /api/:schema/:table/:id - 按主键删除单条数据
+pub async fn delete_record(
+    State(pool): State<PgPool>,
+    mut db: DbConnection,
+    Path((schema, table, id)): Path<(String, String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    tracing::debug!("DELETE /api/{}/{}/{}", schema, table, id);
+
+    // 按主键定位，最多影响一行
+    let mut prefs = Preferences::from_headers(&headers)?;
+    prefs.max_affected = Some(1);
+
+    let mut params = QueryParams::from_mutation_query(query)?;
+    let catalog = load_mutation_catalog(&pool, &schema, &table, &mut params).await?;
+    params.filter_by_key(&keyed_table(&catalog, &schema, &table)?.primary_key, &id)?;
+
+    let builder = mutation_builder(schema.clone(), table.clone(), params, &prefs)?;
+    let (sql, args) = builder.build_delete()?;
+
+    let result = execute_mutation(db.acquire().await?, &sql, args, &prefs, false).await?;
+    single_response(result, &prefs, &schema, &table, &id)
+}
+
+/// 加载表结构：填充列类型，并解析 select（即 RETURNING 字段）中的嵌入资源
+async fn load_mutation_catalog(
+    pool: &PgPool,
+    schema: &str,
+    table: &str,
+    params: &mut QueryParams,
+) -> Result<Arc<DatabaseSchema>> {
+    let catalog = SCHEMA_CACHE
+        .for_table(pool, MAIN_DATABASE_ID, schema, table)
         .await?;
+    params.column_types = catalog.column_types(schema, table).unwrap_or_default();
+    resolve_embeds(&catalog, schema, table, params)?;
+    Ok(catalog)
+}
 
-    // 转换为 JSON
-    let results: Vec<Value> = rows.iter().map(row_to_json).collect();
+/// 写操作是否需要 RETURNING：Prefer: return=representation 或试运行时返回受影响的行
+fn wants_rows(prefs: &Preferences) -> bool {
+    prefs.dry_run || prefs.returning == ReturnMode::Representation
+}
 
-    Ok((StatusCode::OK, Json(Value::Array(results))))
+/// 构建 UPDATE / DELETE / PUT 使用的 SqlBuilder，不需要返回行时省略 RETURNING
+fn mutation_builder(
+    schema: String,
+    table: String,
+    params: QueryParams,
+    prefs: &Preferences,
+) -> Result<SqlBuilder> {
+    let builder = SqlBuilder::new(schema, table, params)?;
+    Ok(if wants_rows(prefs) {
+        builder
+    } else {
+        builder.without_returning()
+    })
 }
 
+/// PUT 写入的列：生成列和非主键的 GENERATED ALWAYS 标识列由数据库计算，不出现在 INSERT 和 SET 中；
+/// 主键是 GENERATED ALWAYS 标识列时，按路径中的主键写入需要 OVERRIDING SYSTEM VALUE（第二个返回值）
+fn replace_columns(info: &TableInfo, row: &Map<String, Value>) -> Result<(Vec<String>, bool)> {
+    let mut columns = Vec::new();
+    let mut overriding = false;
+
+    for column in &info.columns {
+        let is_key = info.primary_key.contains(&column.name);
+        if column.generated || (column.is_identity_always() && !is_key) {
+            if row.contains_key(&column.name) {
+                return Err(AppError::InvalidQuery(format!(
+                    "列 {} 由数据库生成，不能写入",
+                    column.name
+                )));
+            }
+            continue;
+        }
+        overriding |= is_key && column.is_identity_always();
+        columns.push(column.name.clone());
+    }
+
+    Ok((columns, overriding))
+}
+
+/// 查找带主键的表，表不存在时返回 404
+fn keyed_table<'a>(
+    catalog: &'a DatabaseSchema,
+    schema: &str,
+    table: &str,
+) -> Result<&'a TableInfo> {
+    let info = catalog
+        .table(schema, table)
+        .ok_or_else(|| AppError::NotFound(format!("表不存在: {}.{}", schema, table)))?;
+
+    if info.primary_key.is_empty() {
+        return Err(AppError::InvalidQuery(format!(
+            "表 {}.{} 没有主键，不能按主键访问",
+            schema, table
+        )));
+    }
+
+    Ok(info)
+}
+
+fn record_not_found(schema: &str, table: &str, id: &str) -> AppError {
+    AppError::NotFound(format!("记录不存在: {}.{}/{}", schema, table, id))
+}
+
+/// 写操作的执行结果
+enum MutationResult {
+    /// 试运行（已回滚）：行数和示例行
+    DryRun(Value),
+    /// 已提交：影响行数和 RETURNING 返回的行（不需要返回行时为空）
+    Applied { affected: u64, rows: Vec<Value> },
+}
+
+/// 批量写操作的响应：representation 返回受影响的行（singular 时为单个对象），minimal / headers-only 返回 204
+fn mutation_response(result: MutationResult, prefs: &Preferences, singular: bool) -> Response {
+    match result {
+        MutationResult::DryRun(body) => Json(body).into_response(),
+        MutationResult::Applied { rows, .. } if wants_rows(prefs) && singular => (
+            [(header::CONTENT_TYPE, OBJECT_MEDIA_TYPE)],
+            Json(rows.into_iter().next().unwrap_or(Value::Null)),
+        )
+            .into_response(),
+        MutationResult::Applied { rows, .. } if wants_rows(prefs) => {
+            Json(Value::Array(rows)).into_response()
+        }
+        MutationResult::Applied { .. } => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// 单行写操作的响应：没有匹配的行时返回 404，representation 返回单个对象
+fn single_response(
+    result: MutationResult,
+    prefs: &Preferences,
+    schema: &str,
+    table: &str,
+    id: &str,
+) -> Result<Response> {
+    match result {
+        MutationResult::Applied { affected: 0, .. } => Err(record_not_found(schema, table, id)),
+        MutationResult::Applied { rows, .. } if wants_rows(prefs) => {
+            Ok(Json(rows.into_iter().next().unwrap_or(Value::Null)).into_response())
+        }
+        other => Ok(mutation_response(other, prefs, false)),
+    }
+}
+
+/// 客户端是否要求单对象响应（Accept: application/vnd.crestrail.object+json）
+fn wants_object(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| {
+            let media = media.split(';').next().unwrap_or_default().trim();
+            media.eq_ignore_ascii_case(OBJECT_MEDIA_TYPE)
+        })
+}
+
+/// 单对象响应要求结果恰好一行，否则返回 406
+fn expect_single(rows: u64) -> Result<()> {
+    if rows == 1 {
+        return Ok(());
+    }
+    Err(AppError::NotAcceptable(format!(
+        "要求返回单个对象，但结果为 {} 行",
+        rows
+    )))
+}
+
+/// JSON 主键值的文本形式，用于与路径中的主键比较
+fn json_key_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// URL 路径段的百分号编码，只保留 RFC 3986 的非保留字符
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// 取配置上限与 Prefer: max-affected 中较小的一个
+fn affected_limit(configured: Option<i64>, preferred: Option<i64>) -> Option<i64> {
+    match (configured, preferred) {
+        (Some(configured), Some(preferred)) => Some(configured.min(preferred)),
+        (configured, preferred) => configured.or(preferred),
+    }
+}
+
+/// 在事务中执行 UPDATE / DELETE / PUT（在交互式事务中为 SAVEPOINT）
+///
+/// 影响行数超过上限时回滚并报错；试运行时总是回滚，只返回行数和前几行示例。
+/// singular 时影响行数必须恰好为一行，否则回滚并返回 406
+async fn execute_mutation(
+    conn: &mut PgConnection,
+    sql: &str,
+    args: PgArguments,
+    prefs: &Preferences,
+    singular: bool,
+) -> Result<MutationResult> {
+    let limit = affected_limit(*MAX_AFFECTED_ROWS, prefs.max_affected);
+
+    tracing::debug!("执行 SQL: {}", sql);
+
+    let mut tx = conn.begin().await?;
+    let query = sqlx::query_with(sql, args);
+    let (affected, rows) = if wants_rows(prefs) {
+        let rows = query.fetch_all(&mut *tx).await?;
+        (rows.len() as u64, rows)
+    } else {
+        (query.execute(&mut *tx).await?.rows_affected(), Vec::new())
+    };
+
+    if prefs.dry_run {
+        tx.rollback().await?;
+        let sample: Vec<Value> = rows.iter().take(DRY_RUN_SAMPLE_ROWS).map(row_to_json).collect();
+        return Ok(MutationResult::DryRun(serde_json::json!({
+            "dry_run": true,
+            "count": affected,
+            "max_affected": limit,
+            "rows": sample,
+        })));
+    }
+
+    if singular {
+        if let Err(e) = expect_single(affected) {
+            tx.rollback().await?;
+            return Err(e);
+        }
+    }
+
+    if let Some(limit) = limit.filter(|limit| affected > *limit as u64) {
+        tx.rollback().await?;
+        return Err(AppError::InvalidQuery(format!(
+            "本次操作将影响 {} 行，超过上限 {} 行，已回滚",
+            affected, limit
+        )));
+    }
+
+    tx.commit().await?;
+
+    // 转换为 JSON
+    Ok(MutationResult::Applied {
+        affected,
+        rows: rows.iter().map(row_to_json).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema_cache::ColumnInfo;
+
+    #[test]
+    fn test_parse_range() {
+        let range = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, HeaderValue::from_static(value));
+            parse_range(&headers)
+        };
+
+        assert_eq!(range("0-24").unwrap(), Some((0, Some(25))));
+        assert_eq!(range("items=25-49").unwrap(), Some((25, Some(25))));
+        assert_eq!(range("100-").unwrap(), Some((100, None)));
+        assert!(range("24-0").is_err());
+        assert!(range("abc").is_err());
+        assert_eq!(parse_range(&HeaderMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_replace_columns() {
+        let column = |name: &str, generated: bool, identity: Option<&str>| ColumnInfo {
+            name: name.to_string(),
+            data_type: "integer".to_string(),
+            nullable: false,
+            has_default: generated || identity.is_some(),
+            generated,
+            identity: identity.map(str::to_string),
+        };
+        let info = TableInfo {
+            schema: "public".to_string(),
+            name: "order_items".to_string(),
+            kind: "table".to_string(),
+            columns: vec![
+                column("id", false, Some("always")),
+                column("quantity", false, None),
+                column("price", false, None),
+                column("total", true, None),
+                column("seq", false, Some("always")),
+                column("batch", false, Some("by default")),
+            ],
+            primary_key: vec!["id".to_string()],
+        };
+        let row = |value: Value| value.as_object().unwrap().clone();
+
+        // 生成列和非主键的 GENERATED ALWAYS 标识列不写入；主键标识列需要 OVERRIDING SYSTEM VALUE
+        let (columns, overriding) =
+            replace_columns(&info, &row(serde_json::json!({"id": 1, "quantity": 2}))).unwrap();
+        assert_eq!(columns, vec!["id", "quantity", "price", "batch"]);
+        assert!(overriding);
+
+        assert!(replace_columns(&info, &row(serde_json::json!({"id": 1, "total": 9}))).is_err());
+        assert!(replace_columns(&info, &row(serde_json::json!({"id": 1, "seq": 3}))).is_err());
+    }
+
+    #[test]
+    fn test_encode_path_segment() {
+        assert_eq!(encode_path_segment("abc-1_2.3~"), "abc-1_2.3~");
+        assert_eq!(encode_path_segment("a,b/c d"), "a%2Cb%2Fc%20d");
+        assert_eq!(encode_path_segment("订单"), "%E8%AE%A2%E5%8D%95");
+    }
+
+    #[test]
+    fn test_format_content_range() {
+        assert_eq!(format_content_range(0, 25, Some(3512)), "0-24/3512");
+        assert_eq!(format_content_range(50, 10, None), "50-59/*");
+        assert_eq!(format_content_range(0, 0, Some(0)), "*/0");
+    }
+
+    #[test]
+    fn test_affected_limit() {
+        assert_eq!(affected_limit(Some(1000), Some(10)), Some(10));
+        assert_eq!(affected_limit(Some(100), Some(500)), Some(100));
+        assert_eq!(affected_limit(None, Some(10)), Some(10));
+        assert_eq!(affected_limit(Some(100), None), Some(100));
+        assert_eq!(affected_limit(None, None), None);
+    }
+
+    #[test]
+    fn test_wants_object() {
+        let accept = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_static(value));
+            wants_object(&headers)
+        };
+
+        assert!(accept("application/vnd.crestrail.object+json"));
+        assert!(accept("text/html, application/vnd.crestrail.object+json;q=0.9"));
+        assert!(!accept("application/json"));
+        assert!(!wants_object(&HeaderMap::new()));
+
+        assert!(expect_single(1).is_ok());
+        assert!(expect_single(0).is_err());
+        assert!(expect_single(2).is_err());
+    }
+}
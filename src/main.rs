@@ -10,16 +10,22 @@ mod middleware;
 mod models;
 mod monitor_handlers;
 mod pool_manager;
+mod prefer;
 mod query_builder;
+mod row_encoder;
+mod schema_cache;
 mod schema_handlers;
+mod session;
+mod stream;
 mod tenant_handlers;
 mod tenant_models;
 mod transaction;
 
 use axum::{
     extract::State,
+    http::header,
     middleware as axum_middleware,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
 use config::Config;
@@ -49,11 +55,20 @@ async fn main() -> anyhow::Result<()> {
     // 创建数据库连接池
     let pool = db::create_pool(&config.database_url).await?;
 
+    // 加载数据库结构缓存，并监听 crestrail_reload_schema 通知
+    schema_cache::SCHEMA_CACHE
+        .initialize(&pool, schema_cache::MAIN_DATABASE_ID)
+        .await;
+
+    // 定期回滚空闲超时的交互式事务
+    session::spawn_idle_reaper();
+
     // 配置 CORS
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([header::CONTENT_RANGE]);
 
     // 公开路由
     let public_routes = Router::new()
@@ -70,6 +85,9 @@ async fn main() -> anyhow::Result<()> {
         .route("/auth/me", get(auth_handlers::get_me))
         .route("/auth/refresh", post(auth_handlers::refresh_token))
         .route("/auth/change-password", post(auth_handlers::change_password))
+        .route("/transaction/begin", post(session::begin_transaction))
+        .route("/transaction/:id/commit", post(session::commit_transaction))
+        .route("/transaction/:id/rollback", post(session::rollback_transaction))
         .layer(axum_middleware::from_fn(middleware::auth_middleware));
 
     // Schema 管理路由（支持动态数据库连接）
@@ -124,6 +142,8 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/admin/tenant-users/:user_id/:tenant_id", delete(admin_handlers::remove_user_from_tenant))
         // 系统统计
         .route("/api/admin/stats", get(admin_handlers::get_system_stats))
+        // 结构缓存
+        .route("/api/admin/schema-cache/reload", post(admin_handlers::reload_schema_cache))
         .layer(axum_middleware::from_fn(middleware::auth_middleware));
 
     // 数据 CRUD 路由（可选认证）
@@ -132,6 +152,10 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/:schema/:table", post(handlers::create_record))
         .route("/api/:schema/:table", patch(handlers::update_records))
         .route("/api/:schema/:table", delete(handlers::delete_records))
+        .route("/api/:schema/:table/:id", get(handlers::get_record))
+        .route("/api/:schema/:table/:id", put(handlers::replace_record))
+        .route("/api/:schema/:table/:id", patch(handlers::update_record))
+        .route("/api/:schema/:table/:id", delete(handlers::delete_record))
         .layer(axum_middleware::from_fn(middleware::optional_auth_middleware));
 
     // 合并所有路由
@@ -178,7 +202,12 @@ async fn root_handler() -> Result<Json<Value>, AppError> {
                 "refresh": "/auth/refresh",
                 "change_password": "/auth/change-password"
             },
-            "transaction": "/transaction"
+            "transaction": "/transaction",
+            "interactive_transaction": {
+                "begin": "/transaction/begin",
+                "commit": "/transaction/:id/commit",
+                "rollback": "/transaction/:id/rollback"
+            }
         },
         "documentation": "https://github.com/yourusername/crestrail"
     })))
@@ -195,7 +224,6 @@ async fn execute_sql_query(
     Json(req): Json<SqlQueryRequest>,
 ) -> Result<Json<Value>, AppError> {
     use serde_json::json;
-    use sqlx::{Row, Column};
     
     let start = std::time::Instant::now();
     
@@ -212,43 +240,8 @@ async fn execute_sql_query(
         .fetch_all(&pool)
         .await?;
     
-    // 转换为 JSON（使用更智能的类型处理）
-    let results: Vec<Value> = rows
-        .iter()
-        .map(|row| {
-            let mut obj = serde_json::Map::new();
-            for column in row.columns() {
-                let key = column.name().to_string();
-                let idx = column.ordinal();
-                
-                // 尝试不同的类型
-                let value: Value = if let Ok(v) = row.try_get::<String, _>(idx) {
-                    Value::String(v)
-                } else if let Ok(v) = row.try_get::<i32, _>(idx) {
-                    serde_json::json!(v)
-                } else if let Ok(v) = row.try_get::<i64, _>(idx) {
-                    serde_json::json!(v)
-                } else if let Ok(v) = row.try_get::<f64, _>(idx) {
-                    serde_json::json!(v)
-                } else if let Ok(v) = row.try_get::<bool, _>(idx) {
-                    Value::Bool(v)
-                } else if let Ok(v) = row.try_get::<Option<String>, _>(idx) {
-                    v.map(Value::String).unwrap_or(Value::Null)
-                } else if let Ok(v) = row.try_get::<Option<i32>, _>(idx) {
-                    v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-                } else if let Ok(v) = row.try_get::<Option<i64>, _>(idx) {
-                    v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-                } else if let Ok(v) = row.try_get::<serde_json::Value, _>(idx) {
-                    v
-                } else {
-                    Value::Null
-                };
-                
-                obj.insert(key, value);
-            }
-            Value::Object(obj)
-        })
-        .collect();
+    // 转换为 JSON
+    let results: Vec<Value> = rows.iter().map(row_encoder::row_to_json).collect();
     
     let elapsed = start.elapsed().as_millis();
     
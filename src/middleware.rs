@@ -8,7 +8,7 @@ use sqlx::PgPool;
 
 use crate::auth::{verify_token, Claims};
 use crate::error::AppError;
-use crate::pool_manager::{DatabaseConfig, POOL_MANAGER};
+use crate::pool_manager::{DatabaseConfig, DatabaseId, POOL_MANAGER};
 
 /// JWT 认证中间件
 pub async fn auth_middleware(mut req: Request, next: Next) -> Result<Response, AppError> {
@@ -108,8 +108,9 @@ pub async fn dynamic_db_middleware(
                 let pool = POOL_MANAGER.get_or_create_pool(config).await?;
                 
                 tracing::info!("成功切换到数据库连接 ID: {}", database_id);
-                // 将动态连接池存入请求扩展
+                // 将动态连接池及其 ID 存入请求扩展
                 req.extensions_mut().insert(pool);
+                req.extensions_mut().insert(DatabaseId(database_id));
             } else {
                 tracing::warn!("未找到数据库连接配置: ID={}", database_id);
             }
@@ -168,7 +169,7 @@ mod tests {
     #[test]
     fn test_has_role() {
         let claims = Claims {
-            sub: "123".to_string(),
+            sub: 123,
             email: "test@example.com".to_string(),
             role: "user".to_string(),
             exp: 9999999999,
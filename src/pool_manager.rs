@@ -4,10 +4,15 @@ use sqlx::postgres::{PgPool, PgPoolOptions};
 use std::time::Duration;
 
 use crate::error::{AppError, Result};
+use crate::schema_cache::SCHEMA_CACHE;
 
 /// 全局连接池管理器
 pub static POOL_MANAGER: Lazy<PoolManager> = Lazy::new(|| PoolManager::new());
 
+/// 当前请求使用的数据库 ID（由动态数据库中间件写入请求扩展）
+#[derive(Debug, Clone, Copy)]
+pub struct DatabaseId(pub i32);
+
 /// 数据库连接配置
 #[derive(Debug, Clone)]
 pub struct DatabaseConfig {
@@ -55,6 +60,7 @@ impl PoolManager {
                 // 连接池不健康，移除它
                 tracing::warn!("连接池 {} 不健康，将重新创建", config.id);
                 self.pools.remove(&config.id);
+                SCHEMA_CACHE.remove(config.id);
             }
         }
 
@@ -80,11 +86,20 @@ impl PoolManager {
         // 缓存连接池
         self.pools.insert(config.id, pool.clone());
 
+        // 加载数据库结构缓存并监听刷新通知
+        SCHEMA_CACHE.initialize(&pool, config.id).await;
+
         Ok(pool)
     }
 
+    /// 获取已创建的连接池
+    pub fn get_pool(&self, database_id: i32) -> Option<PgPool> {
+        self.pools.get(&database_id).map(|pool| pool.clone())
+    }
+
     /// 移除连接池
     pub async fn remove_pool(&self, database_id: i32) {
+        SCHEMA_CACHE.remove(database_id);
         if let Some((_, pool)) = self.pools.remove(&database_id) {
             tracing::info!("关闭连接池: database_id={}", database_id);
             pool.close().await;
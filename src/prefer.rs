@@ -0,0 +1,219 @@
+use axum::http::HeaderMap;
+use serde::Deserialize;
+
+use crate::error::{AppError, Result};
+
+/// 总行数统计方式（Prefer: count=...）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountMode {
+    /// 使用相同过滤条件执行 COUNT(*)
+    Exact,
+    /// 取查询计划器估算的行数（EXPLAIN）
+    Planned,
+    /// 取 pg_class.reltuples 中的表级估算值
+    Estimated,
+}
+
+/// 写操作的响应内容（Prefer: return=...）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReturnMode {
+    /// 不返回响应体
+    Minimal,
+    /// 只返回 Location 响应头（插入单条记录时）
+    HeadersOnly,
+    /// 返回受影响的行，字段由 select 指定
+    #[default]
+    Representation,
+}
+
+/// 读取接口 JSON 响应体的生成方式（Prefer: render=...）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// 由 PostgreSQL 的 row_to_json 逐行生成 JSON 文本
+    Database,
+    /// 逐行读取后由服务端的行编码器生成
+    Application,
+}
+
+/// 插入时的主键 / 唯一键冲突处理方式（Prefer: resolution=...）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Resolution {
+    /// 冲突时以新数据更新已有行（ON CONFLICT DO UPDATE）
+    MergeDuplicates,
+    /// 冲突时保留已有行（ON CONFLICT DO NOTHING）
+    IgnoreDuplicates,
+}
+
+/// Prefer 请求头解析结果
+#[derive(Debug, Clone, Default)]
+pub struct Preferences {
+    pub count: Option<CountMode>,
+    /// 响应体的生成方式，未指定时取 SERVER_SIDE_JSON 配置
+    pub render: Option<RenderMode>,
+    /// 以分块 JSON 数组流式返回查询结果（Prefer: stream）
+    pub stream: bool,
+    /// 插入冲突时的处理方式
+    pub resolution: Option<Resolution>,
+    /// 写操作的响应内容
+    pub returning: ReturnMode,
+    /// 本次 PATCH / DELETE 允许影响的最大行数（Prefer: max-affected=N）
+    pub max_affected: Option<i64>,
+    /// 执行后统计被修改的行并回滚（Prefer: dry-run），触发器照常执行、行锁持有到回滚
+    pub dry_run: bool,
+    /// 允许不带过滤条件更新整张表，仅对管理员生效（Prefer: allow-full-table）
+    pub allow_full_table: bool,
+}
+
+impl Preferences {
+    /// 从请求头解析（可能有多个 Prefer 头，每个头内以逗号分隔）
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self> {
+        let mut prefs = Preferences::default();
+
+        for value in headers.get_all("prefer") {
+            let value = value
+                .to_str()
+                .map_err(|_| AppError::InvalidQuery("Prefer 请求头包含非法字符".to_string()))?;
+
+            for token in value.split(',') {
+                let (name, arg) = match token.split_once('=') {
+                    Some((name, arg)) => (name.trim(), arg.trim()),
+                    None => (token.trim(), ""),
+                };
+
+                // 按 RFC 7240，无法识别的偏好直接忽略
+                match name {
+                    "count" => {
+                        prefs.count = Some(match arg {
+                            "exact" => CountMode::Exact,
+                            "planned" => CountMode::Planned,
+                            "estimated" => CountMode::Estimated,
+                            _ => {
+                                return Err(AppError::InvalidQuery(format!(
+                                    "无效的 count 偏好: {}，可选 exact / planned / estimated",
+                                    arg
+                                )))
+                            }
+                        });
+                    }
+                    "return" => {
+                        prefs.returning = match arg {
+                            "minimal" => ReturnMode::Minimal,
+                            "headers-only" => ReturnMode::HeadersOnly,
+                            "representation" => ReturnMode::Representation,
+                            _ => {
+                                return Err(AppError::InvalidQuery(format!(
+                                    "无效的 return 偏好: {}，可选 minimal / headers-only / representation",
+                                    arg
+                                )))
+                            }
+                        };
+                    }
+                    "render" => {
+                        prefs.render = Some(match arg {
+                            "database" => RenderMode::Database,
+                            "application" => RenderMode::Application,
+                            _ => {
+                                return Err(AppError::InvalidQuery(format!(
+                                    "无效的 render 偏好: {}，可选 database / application",
+                                    arg
+                                )))
+                            }
+                        });
+                    }
+                    "resolution" => {
+                        prefs.resolution = Some(match arg {
+                            "merge-duplicates" => Resolution::MergeDuplicates,
+                            "ignore-duplicates" => Resolution::IgnoreDuplicates,
+                            _ => {
+                                return Err(AppError::InvalidQuery(format!(
+                                    "无效的 resolution 偏好: {}，可选 merge-duplicates / ignore-duplicates",
+                                    arg
+                                )))
+                            }
+                        });
+                    }
+                    "max-affected" => {
+                        let limit = arg.parse::<i64>().ok().filter(|limit| *limit > 0);
+                        prefs.max_affected = Some(limit.ok_or_else(|| {
+                            AppError::InvalidQuery(format!(
+                                "无效的 max-affected 偏好: {}，必须是正整数",
+                                arg
+                            ))
+                        })?);
+                    }
+                    "dry-run" => prefs.dry_run = true,
+                    "stream" => prefs.stream = true,
+                    "allow-full-table" => prefs.allow_full_table = true,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(prefs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_parse_count_preference() {
+        let mut headers = HeaderMap::new();
+        headers.append("prefer", HeaderValue::from_static("return=minimal, count=planned"));
+        let prefs = Preferences::from_headers(&headers).unwrap();
+        assert_eq!(prefs.count, Some(CountMode::Planned));
+        assert_eq!(prefs.returning, ReturnMode::Minimal);
+
+        let mut headers = HeaderMap::new();
+        headers.append("prefer", HeaderValue::from_static("count=maybe"));
+        assert!(Preferences::from_headers(&headers).is_err());
+
+        let defaults = Preferences::from_headers(&HeaderMap::new()).unwrap();
+        assert_eq!(defaults.count, None);
+        assert_eq!(defaults.returning, ReturnMode::Representation);
+
+        let mut headers = HeaderMap::new();
+        headers.append("prefer", HeaderValue::from_static("resolution=ignore-duplicates"));
+        let prefs = Preferences::from_headers(&headers).unwrap();
+        assert_eq!(prefs.resolution, Some(Resolution::IgnoreDuplicates));
+
+        let mut headers = HeaderMap::new();
+        headers.append("prefer", HeaderValue::from_static("resolution=overwrite"));
+        assert!(Preferences::from_headers(&headers).is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.append("prefer", HeaderValue::from_static("render=application"));
+        let prefs = Preferences::from_headers(&headers).unwrap();
+        assert_eq!(prefs.render, Some(RenderMode::Application));
+        assert_eq!(defaults.render, None);
+        assert!(!defaults.stream);
+
+        let mut headers = HeaderMap::new();
+        headers.append("prefer", HeaderValue::from_static("render=xml"));
+        assert!(Preferences::from_headers(&headers).is_err());
+    }
+
+    #[test]
+    fn test_parse_mutation_preferences() {
+        let mut headers = HeaderMap::new();
+        headers.append("prefer", HeaderValue::from_static("max-affected=50, dry-run"));
+        headers.append("prefer", HeaderValue::from_static("allow-full-table"));
+        let prefs = Preferences::from_headers(&headers).unwrap();
+        assert_eq!(prefs.max_affected, Some(50));
+        assert!(prefs.dry_run);
+        assert!(prefs.allow_full_table);
+
+        let defaults = Preferences::from_headers(&HeaderMap::new()).unwrap();
+        assert_eq!(defaults.max_affected, None);
+        assert!(!defaults.dry_run && !defaults.allow_full_table);
+
+        for value in ["max-affected=0", "max-affected=-1", "max-affected=many"] {
+            let mut headers = HeaderMap::new();
+            headers.append("prefer", HeaderValue::from_static(value));
+            assert!(Preferences::from_headers(&headers).is_err());
+        }
+    }
+}
@@ -1,13 +1,24 @@
 use crate::error::{AppError, Result};
+use crate::prefer::Resolution;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use sqlx::postgres::PgArguments;
 use sqlx::Arguments;
 use std::collections::HashMap;
 
+/// 游标分页时附加到结果中的排序键列前缀（响应前会移除）
+pub const CURSOR_COLUMN_PREFIX: &str = "_cursor_";
+
+/// 单条语句最多绑定的参数个数（PostgreSQL 协议上限）
+const MAX_BIND_PARAMS: usize = 65535;
+
+/// 批量插入时单条 INSERT 最多包含的行数
+const INSERT_CHUNK_ROWS: usize = 1000;
+
 /// 查询参数解析器
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct QueryParams {
-    /// WHERE 条件
-    pub filters: Vec<Filter>,
+    /// WHERE 条件（顶层之间以 AND 连接）
+    pub filters: Vec<FilterNode>,
     /// 排序字段
     pub order_by: Vec<OrderBy>,
     /// 分页限制
@@ -15,7 +26,169 @@ pub struct QueryParams {
     /// 偏移量
     pub offset: Option<i64>,
     /// 选择的字段
-    pub select: Option<Vec<String>>,
+    pub select: Option<Vec<SelectItem>>,
+    /// 游标分页（cursor 参数，首页传空字符串）
+    pub cursor: Option<Cursor>,
+    /// 分组后的过滤条件（having 参数，只能引用聚合函数）
+    pub having: Vec<FilterNode>,
+    /// INSERT 的列集合（columns 参数），数据中缺失的键取列默认值
+    pub columns: Option<Vec<String>>,
+    /// upsert 的冲突列（on_conflict 参数），未指定时由调用方填充为主键
+    pub on_conflict: Option<Vec<String>>,
+    /// 列名 -> 列类型，由 schema 缓存填充；为空时参数按文本绑定
+    pub column_types: HashMap<String, String>,
+}
+
+/// 游标（keyset）分页
+#[derive(Debug, Clone, Default)]
+pub struct Cursor {
+    /// 上一页最后一行的排序键值（按 order_by 顺序），首页为空
+    pub values: Vec<String>,
+}
+
+impl Cursor {
+    /// 解码不透明的游标字符串（base64url 编码的 JSON 数组）
+    pub fn decode(token: &str) -> Result<Self> {
+        if token.is_empty() {
+            return Ok(Cursor::default());
+        }
+
+        let invalid = || AppError::InvalidQuery("无效的 cursor".to_string());
+        let bytes = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        let values: Vec<String> = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+
+        if values.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Cursor { values })
+    }
+
+    /// 将排序键值编码为不透明的游标字符串
+    pub fn encode(values: &[String]) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(values).unwrap_or_default())
+    }
+}
+
+/// SELECT 字段项
+#[derive(Debug, Clone)]
+pub enum SelectItem {
+    /// 普通列，`*` 表示全部列
+    Column(String),
+    /// 通过外键嵌入的关联资源，例如 orders(id,total)
+    Embed(Box<Embed>),
+    /// 聚合函数，例如 sum(amount) 或 total:sum(amount)
+    Aggregate(Aggregate),
+    /// JSON 路径，例如 attrs->color 或 attrs->>size
+    JsonPath(JsonPathSelect),
+}
+
+/// select 中的 JSON 路径
+#[derive(Debug, Clone)]
+pub struct JsonPathSelect {
+    pub column: String,
+    pub path: Vec<JsonPathSegment>,
+    /// 输出字段名，默认为路径最后一段
+    pub alias: String,
+}
+
+/// JSON 路径中的一段
+#[derive(Debug, Clone)]
+pub struct JsonPathSegment {
+    /// 对象键；纯数字时作为数组下标
+    pub key: String,
+    /// true 为 ->>（取文本），false 为 ->（取 JSON）
+    pub as_text: bool,
+}
+
+/// 聚合字段
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// 聚合的列，count() 为 `*`
+    pub column: String,
+    /// 输出字段名，默认为函数名
+    pub alias: String,
+}
+
+/// 允许使用的聚合函数（白名单）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    /// 按名称查找聚合函数，不在白名单中的返回 None
+    pub fn parse(name: &str) -> Option<Self> {
+        let function = match name {
+            "count" => AggregateFunction::Count,
+            "sum" => AggregateFunction::Sum,
+            "avg" => AggregateFunction::Avg,
+            "min" => AggregateFunction::Min,
+            "max" => AggregateFunction::Max,
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    /// 函数名（同时作为默认输出字段名）
+    pub fn name(self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+        }
+    }
+
+    /// 生成聚合表达式，column 为 `*` 时生成 COUNT(*)；qualifier 为列引用前缀（见 compile_select）
+    pub fn to_sql(self, qualifier: &str, column: &str) -> String {
+        let name = self.name().to_uppercase();
+        if column == "*" {
+            format!("{}(*)", name)
+        } else {
+            format!("{}({}\"{}\")", name, qualifier, column)
+        }
+    }
+
+    /// 聚合结果是否为数值（count / sum / avg），比较时按 numeric 绑定参数
+    fn is_numeric(self) -> bool {
+        matches!(
+            self,
+            AggregateFunction::Count | AggregateFunction::Sum | AggregateFunction::Avg
+        )
+    }
+}
+
+/// 嵌入资源
+#[derive(Debug, Clone)]
+pub struct Embed {
+    /// 关联表名（同时作为输出字段名）
+    pub table: String,
+    /// 外键提示（约束名或外键列名），用于区分同一对表之间的多条关系
+    pub hint: Option<String>,
+    /// 提示带 .inverse 后缀：只按一对多方向关联（自引用外键默认按多对一方向关联）
+    pub inverse: bool,
+    /// 嵌入资源自身的 select / 过滤 / 排序 / 分页
+    pub params: QueryParams,
+    /// 关联方式，由外键元数据解析后填充
+    pub join: Option<EmbedJoin>,
+}
+
+/// 嵌入资源的关联方式
+#[derive(Debug, Clone)]
+pub struct EmbedJoin {
+    /// 关联表所在 schema
+    pub schema: String,
+    /// true 为一对多（返回数组），false 为多对一（返回对象）
+    pub to_many: bool,
+    /// (关联表列, 父表列) 列对
+    pub columns: Vec<(String, String)>,
 }
 
 /// 过滤条件
@@ -24,6 +197,23 @@ pub struct Filter {
     pub column: String,
     pub operator: FilterOperator,
     pub value: String,
+    /// having 条件中作用于列的聚合函数
+    pub aggregate: Option<AggregateFunction>,
+    /// 作用于列内 JSON 路径时的路径段
+    pub path: Vec<JsonPathSegment>,
+}
+
+/// 过滤条件树（支持 and / or / not 嵌套）
+#[derive(Debug, Clone)]
+pub enum FilterNode {
+    /// 单个条件
+    Condition(Filter),
+    /// 所有子条件同时满足
+    And(Vec<FilterNode>),
+    /// 任一子条件满足
+    Or(Vec<FilterNode>),
+    /// 取反
+    Not(Box<FilterNode>),
 }
 
 /// 过滤操作符
@@ -39,6 +229,32 @@ pub enum FilterOperator {
     Ilike,       // 不区分大小写模糊匹配 (ILIKE)
     In,          // IN 查询
     Is,          // IS (用于 NULL)
+    Fts(Option<String>),   // 全文检索 to_tsquery，可选语言配置
+    Plfts(Option<String>), // 全文检索 plainto_tsquery
+    Phfts(Option<String>), // 全文检索 phraseto_tsquery
+    Wfts(Option<String>),  // 全文检索 websearch_to_tsquery
+    Cs,          // 包含 (@>)
+    Cd,          // 被包含 (<@)
+    Ov,          // 重叠 (&&)
+    Sl,          // 范围严格在左侧 (<<)
+    Sr,          // 范围严格在右侧 (>>)
+    Nxl,         // 范围不延伸到右侧 (&<)
+    Nxr,         // 范围不延伸到左侧 (&>)
+    Adj,         // 范围相邻 (-|-)
+    HasKey,      // JSON 对象包含键 (?)
+}
+
+impl FilterOperator {
+    /// 全文检索操作符对应的 tsquery 函数及语言配置
+    fn text_search(&self) -> Option<(&'static str, Option<&str>)> {
+        match self {
+            FilterOperator::Fts(language) => Some(("to_tsquery", language.as_deref())),
+            FilterOperator::Plfts(language) => Some(("plainto_tsquery", language.as_deref())),
+            FilterOperator::Phfts(language) => Some(("phraseto_tsquery", language.as_deref())),
+            FilterOperator::Wfts(language) => Some(("websearch_to_tsquery", language.as_deref())),
+            _ => None,
+        }
+    }
 }
 
 /// 排序方向
@@ -46,14 +262,34 @@ pub enum FilterOperator {
 pub struct OrderBy {
     pub column: String,
     pub ascending: bool,
+    /// order=rank(列) 时指定按哪一列的全文检索条件计算相关度
+    pub rank: Option<String>,
 }
 
 impl QueryParams {
     /// 从 URL 查询参数解析
-    pub fn from_query_map(query: HashMap<String, String>) -> Result<Self> {
+    pub fn from_query_map(mut query: HashMap<String, String>) -> Result<Self> {
         let mut params = QueryParams::default();
 
+        // 先确定嵌入资源，orders.status.eq=paid 这类参数需要分发给对应的嵌入资源
+        let raw_select = query.remove("select");
+        let embed_names = match &raw_select {
+            Some(select) => Self::embed_names(select)?,
+            None => Vec::new(),
+        };
+        let mut embed_queries: HashMap<String, HashMap<String, String>> = HashMap::new();
+
         for (key, value) in query.iter() {
+            if let Some((first, rest)) = key.split_once('.') {
+                if embed_names.iter().any(|name| name == first) {
+                    embed_queries
+                        .entry(first.to_string())
+                        .or_default()
+                        .insert(rest.to_string(), value.clone());
+                    continue;
+                }
+            }
+
             match key.as_str() {
                 "limit" => {
                     params.limit = Some(
@@ -72,13 +308,24 @@ impl QueryParams {
                 "order" => {
                     params.order_by = Self::parse_order(value)?;
                 }
-                "select" => {
-                    params.select = Some(
-                        value
-                            .split(',')
-                            .map(|s| Self::sanitize_identifier(s.trim()))
-                            .collect::<Result<Vec<_>>>()?,
-                    );
+                "cursor" => {
+                    params.cursor = Some(Cursor::decode(value)?);
+                }
+                "and" | "or" | "not.and" | "not.or" => {
+                    // 逻辑组合: or=(status.eq.pending,and(amount.gt.100,region.eq.eu))
+                    params.filters.push(Self::parse_logic_group(key, value, false)?);
+                }
+                "having" => {
+                    // 分组过滤: having=(sum(amount).gt.1000,count().gte.5)
+                    params.having.push(Self::parse_logic_group("and", value, true)?);
+                }
+                "columns" => {
+                    // 批量插入的列集合: columns=name,email,age
+                    params.columns = Some(Self::parse_column_list(value)?);
+                }
+                "on_conflict" => {
+                    // upsert 的冲突列: on_conflict=sku,warehouse
+                    params.on_conflict = Some(Self::parse_column_list(value)?);
                 }
                 _ => {
                     // 处理过滤条件
@@ -89,11 +336,367 @@ impl QueryParams {
             }
         }
 
+        if let Some(select) = raw_select {
+            params.select = Some(Self::parse_select(&select, &mut embed_queries)?);
+        }
+
+        if !params.having.is_empty() && !params.is_grouped() {
+            return Err(AppError::InvalidQuery(
+                "having 只能与 select 中的聚合函数一起使用".to_string(),
+            ));
+        }
+
         Ok(params)
     }
 
+    /// 解析 UPDATE / DELETE 的查询参数
+    ///
+    /// 过滤语法与查询相同，但无法识别的条件直接报错而不是忽略，避免条件被丢弃后影响到预期之外的行；
+    /// 分页、排序、游标和 having 对写操作没有意义，同样拒绝。
+    pub fn from_mutation_query(query: HashMap<String, String>) -> Result<Self> {
+        for (key, value) in query.iter() {
+            match key.as_str() {
+                "limit" | "offset" | "order" | "cursor" | "having" | "columns" | "on_conflict" => {
+                    return Err(AppError::InvalidQuery(format!(
+                        "{} 不能用于 UPDATE / DELETE",
+                        key
+                    )));
+                }
+                "select" | "and" | "or" | "not.and" | "not.or" => {}
+                _ => {
+                    if Self::parse_filter(key, value)?.is_none() {
+                        return Err(AppError::InvalidQuery(format!(
+                            "不支持的过滤条件: {}",
+                            key
+                        )));
+                    }
+                }
+            }
+        }
+
+        Self::from_query_map(query)
+    }
+
+    /// 解析逗号分隔的列名列表
+    fn parse_column_list(value: &str) -> Result<Vec<String>> {
+        value
+            .split(',')
+            .map(|column| Self::sanitize_identifier(column.trim()))
+            .collect()
+    }
+
+    /// select 中是否包含聚合函数（包含时按非聚合列分组）
+    pub fn is_grouped(&self) -> bool {
+        self.select.as_ref().is_some_and(|items| {
+            items
+                .iter()
+                .any(|item| matches!(item, SelectItem::Aggregate(_)))
+        })
+    }
+
+    /// select 中聚合与 JSON 路径的输出字段名（可以在 order 中引用）
+    fn output_aliases(&self) -> Vec<&str> {
+        self.select
+            .iter()
+            .flatten()
+            .filter_map(|item| match item {
+                SelectItem::Aggregate(aggregate) => Some(aggregate.alias.as_str()),
+                SelectItem::JsonPath(select) => Some(select.alias.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// 拆分路径中的主键值，复合主键以逗号分隔并按主键列顺序对应: 42,3 -> [(order_id, 42), (line, 3)]
+    ///
+    /// 单列主键不拆分，值中可以包含逗号
+    pub fn split_key<'a>(
+        primary_key: &'a [String],
+        id: &'a str,
+    ) -> Result<Vec<(&'a String, &'a str)>> {
+        let values: Vec<&str> = match primary_key {
+            [_] => vec![id],
+            _ => id.split(',').collect(),
+        };
+        if values.len() != primary_key.len() || values.iter().any(|value| value.is_empty()) {
+            return Err(AppError::InvalidQuery(format!(
+                "主键值 {} 与主键 ({}) 不匹配",
+                id,
+                primary_key.join(", ")
+            )));
+        }
+
+        Ok(primary_key.iter().zip(values).collect())
+    }
+
+    /// 按主键定位单行，为每个主键列追加等值条件
+    pub fn filter_by_key(&mut self, primary_key: &[String], id: &str) -> Result<()> {
+        for (column, value) in Self::split_key(primary_key, id)? {
+            self.filter_eq(column, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// 追加列等值过滤条件
+    pub fn filter_eq(&mut self, column: &str, value: &str) -> Result<()> {
+        self.filters.push(FilterNode::Condition(Filter {
+            column: Self::sanitize_identifier(column)?,
+            operator: FilterOperator::Eq,
+            value: value.to_string(),
+            aggregate: None,
+            path: Vec::new(),
+        }));
+        Ok(())
+    }
+
+    /// 为游标分页补全排序键：在 order 列之后追加主键列，方向与 order 保持一致
+    pub fn prepare_keyset(&mut self, primary_key: &[String]) -> Result<()> {
+        let cursor = match &self.cursor {
+            Some(cursor) => cursor,
+            None => return Ok(()),
+        };
+
+        if self.is_grouped() {
+            return Err(AppError::InvalidQuery(
+                "cursor 不能与聚合查询同时使用".to_string(),
+            ));
+        }
+
+        if self.offset.is_some() {
+            return Err(AppError::InvalidQuery(
+                "cursor 不能与 offset 同时使用".to_string(),
+            ));
+        }
+
+        if self.order_by.iter().any(|order| order.rank.is_some()) {
+            return Err(AppError::InvalidQuery(
+                "cursor 不能与 rank 排序同时使用".to_string(),
+            ));
+        }
+
+        if primary_key.is_empty() {
+            return Err(AppError::InvalidQuery("游标分页要求表具有主键".to_string()));
+        }
+
+        // 行值比较 (a, b) > ($1, $2) 要求所有列方向一致
+        let ascending = self.order_by.first().is_none_or(|order| order.ascending);
+        if self.order_by.iter().any(|order| order.ascending != ascending) {
+            return Err(AppError::InvalidQuery(
+                "游标分页要求所有排序列方向一致".to_string(),
+            ));
+        }
+
+        for column in primary_key {
+            if !self.order_by.iter().any(|order| &order.column == column) {
+                self.order_by.push(OrderBy {
+                    column: column.clone(),
+                    ascending,
+                    rank: None,
+                });
+            }
+        }
+
+        if !cursor.values.is_empty() && cursor.values.len() != self.order_by.len() {
+            return Err(AppError::InvalidQuery("cursor 与当前排序不匹配".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// 解析 select: id,name,orders(id,total,items(*))
+    fn parse_select(
+        value: &str,
+        embed_queries: &mut HashMap<String, HashMap<String, String>>,
+    ) -> Result<Vec<SelectItem>> {
+        let items = Self::split_top_level(value)?
+            .iter()
+            .map(|item| {
+                let item = item.trim();
+                if let Some(computed) = Self::parse_computed(item)? {
+                    return Ok(computed);
+                }
+                match Self::split_embed(item) {
+                    Some((name, hint, inner)) => {
+                        let mut sub_query = embed_queries.remove(name).unwrap_or_default();
+                        sub_query.insert("select".to_string(), inner.to_string());
+                        let (hint, inverse) = match hint {
+                            Some(hint) => match hint.strip_suffix(".inverse") {
+                                Some(hint) => (Some(hint), true),
+                                None => (Some(hint), false),
+                            },
+                            None => (None, false),
+                        };
+                        Ok(SelectItem::Embed(Box::new(Embed {
+                            table: Self::sanitize_identifier(name)?,
+                            hint: hint.map(Self::sanitize_identifier).transpose()?,
+                            inverse,
+                            params: Self::from_query_map(sub_query)?,
+                            join: None,
+                        })))
+                    }
+                    None if item == "*" => Ok(SelectItem::Column(item.to_string())),
+                    None => Ok(SelectItem::Column(Self::sanitize_identifier(item)?)),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // 聚合查询按普通列分组，* 和嵌入资源无法出现在 GROUP BY 结果中
+        if items.iter().any(|item| matches!(item, SelectItem::Aggregate(_))) {
+            for item in &items {
+                match item {
+                    SelectItem::Column(column) if column == "*" => {
+                        return Err(AppError::InvalidQuery(
+                            "聚合查询不能使用 *，请列出分组列".to_string(),
+                        ));
+                    }
+                    SelectItem::Embed(embed) => {
+                        return Err(AppError::InvalidQuery(format!(
+                            "聚合查询不能同时嵌入资源: {}",
+                            embed.table
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// 解析聚合项或 JSON 路径，可带别名: count()、total:sum(amount)、attrs->>size、size:attrs->>size
+    ///
+    /// 不是这两种形式时返回 None
+    fn parse_computed(item: &str) -> Result<Option<SelectItem>> {
+        let (alias, expr) = match item.split_once(':') {
+            Some((alias, expr)) => (Some(Self::sanitize_identifier(alias)?), expr),
+            None => (None, item),
+        };
+
+        if let Some((function, column)) = Self::split_aggregate(expr)? {
+            return Ok(Some(SelectItem::Aggregate(Aggregate {
+                function,
+                column,
+                alias: alias.unwrap_or_else(|| function.name().to_string()),
+            })));
+        }
+
+        if expr.contains("->") {
+            let (column, path) = Self::parse_column_path(expr)?;
+            let last = path.last().ok_or_else(|| {
+                AppError::InvalidQuery(format!("无效的 JSON 路径: {}", expr))
+            })?;
+            // 默认以路径最后一段作为输出字段名
+            let alias = alias.unwrap_or_else(|| last.key.clone());
+            return Ok(Some(SelectItem::JsonPath(JsonPathSelect {
+                column,
+                path,
+                alias,
+            })));
+        }
+
+        match alias {
+            Some(_) => Err(AppError::InvalidQuery(format!(
+                "别名只能用于聚合函数或 JSON 路径: {}",
+                item
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// 解析列引用中的 JSON 路径: attrs->dims->>width -> ("attrs", [dims, width])
+    ///
+    /// 路径段只允许字母、数字、下划线和连字符，编译时以参数绑定；->> 只能出现在最后一段
+    fn parse_column_path(expr: &str) -> Result<(String, Vec<JsonPathSegment>)> {
+        let mut parts = expr.split("->");
+        let column = Self::sanitize_identifier(parts.next().unwrap_or_default())?;
+
+        let mut path = Vec::new();
+        for part in parts {
+            if path.iter().any(|segment: &JsonPathSegment| segment.as_text) {
+                return Err(AppError::InvalidQuery(format!(
+                    "->> 只能用于 JSON 路径的最后一段: {}",
+                    expr
+                )));
+            }
+
+            let (key, as_text) = match part.strip_prefix('>') {
+                Some(key) => (key, true),
+                None => (part, false),
+            };
+
+            let valid = !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+            if !valid {
+                return Err(AppError::InvalidQuery(format!(
+                    "无效的 JSON 路径: {}. 路径段只允许字母、数字、下划线和连字符",
+                    expr
+                )));
+            }
+
+            path.push(JsonPathSegment {
+                key: key.to_string(),
+                as_text,
+            });
+        }
+
+        Ok((column, path))
+    }
+
+    /// 拆分聚合表达式 func(column)，函数名不在白名单中时返回 None
+    fn split_aggregate(expr: &str) -> Result<Option<(AggregateFunction, String)>> {
+        let (name, column) = match expr
+            .split_once('(')
+            .and_then(|(name, rest)| Some((name, rest.strip_suffix(')')?)))
+        {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+
+        let function = match AggregateFunction::parse(&name.to_lowercase()) {
+            Some(function) => function,
+            None => return Ok(None),
+        };
+
+        let column = match column.trim() {
+            "" | "*" if function == AggregateFunction::Count => "*".to_string(),
+            "" | "*" => {
+                return Err(AppError::InvalidQuery(format!(
+                    "{} 必须指定列",
+                    function.name()
+                )))
+            }
+            column => Self::sanitize_identifier(column)?,
+        };
+
+        Ok(Some((function, column)))
+    }
+
+    /// 拆分嵌入项: name(inner) 或 name!hint(inner)，hint 可带 .inverse 后缀
+    fn split_embed(item: &str) -> Option<(&str, Option<&str>, &str)> {
+        let (head, rest) = item.split_once('(')?;
+        let inner = rest.strip_suffix(')')?;
+        let (name, hint) = match head.split_once('!') {
+            Some((name, hint)) => (name, Some(hint)),
+            None => (head, None),
+        };
+        Some((name, hint, inner))
+    }
+
+    /// select 中顶层嵌入资源的名称
+    fn embed_names(select: &str) -> Result<Vec<String>> {
+        Ok(Self::split_top_level(select)?
+            .iter()
+            .map(|item| item.trim())
+            .filter(|item| !matches!(Self::parse_computed(item), Ok(Some(_))))
+            .filter_map(|item| Self::split_embed(item).map(|(name, _, _)| name.to_string()))
+            .collect())
+    }
+
     /// 解析过滤条件
-    fn parse_filter(key: &str, value: &str) -> Result<Option<Filter>> {
+    fn parse_filter(key: &str, value: &str) -> Result<Option<FilterNode>> {
         // 支持的格式:
         // column=value (等于)
         // column.eq=value (等于)
@@ -106,37 +709,261 @@ impl QueryParams {
         // column.ilike=value (不区分大小写模糊匹配)
         // column.in=value1,value2,value3 (IN 查询)
         // column.is=null (IS NULL)
+        // column.not.eq=value (任意操作符前加 not. 表示取反)
 
         let parts: Vec<&str> = key.split('.').collect();
 
-        let (column, operator) = match parts.len() {
-            1 => (parts[0], FilterOperator::Eq),
-            2 => {
-                let op = match parts[1] {
-                    "eq" => FilterOperator::Eq,
-                    "neq" => FilterOperator::Neq,
-                    "gt" => FilterOperator::Gt,
-                    "gte" => FilterOperator::Gte,
-                    "lt" => FilterOperator::Lt,
-                    "lte" => FilterOperator::Lte,
-                    "like" => FilterOperator::Like,
-                    "ilike" => FilterOperator::Ilike,
-                    "in" => FilterOperator::In,
-                    "is" => FilterOperator::Is,
-                    _ => return Ok(None), // 忽略不支持的操作符
-                };
-                (parts[0], op)
-            }
+        let (column, negated, op) = match parts.as_slice() {
+            [column] => (*column, false, "eq"),
+            [column, op] => (*column, false, *op),
+            [column, "not", op] => (*column, true, *op),
             _ => return Ok(None),
         };
 
-        Self::sanitize_identifier(column)?;
+        let operator = match Self::parse_operator(op)? {
+            Some(operator) => operator,
+            None => return Ok(None), // 忽略不支持的操作符
+        };
+
+        Ok(Some(Self::condition(column, None, operator, value, negated)?))
+    }
+
+    /// 构建单个条件节点（negated 时包裹 NOT），aggregate 仅用于 having
+    fn condition(
+        column: &str,
+        aggregate: Option<AggregateFunction>,
+        operator: FilterOperator,
+        value: &str,
+        negated: bool,
+    ) -> Result<FilterNode> {
+        let (column, path) = match aggregate {
+            Some(_) if column == "*" => (column.to_string(), Vec::new()),
+            Some(_) => (Self::sanitize_identifier(column)?, Vec::new()),
+            None => Self::parse_column_path(column)?,
+        };
+
+        // IN 列表允许写成 (a,b,c)
+        let value = match operator {
+            FilterOperator::In => Self::unwrap_list(value),
+            _ => value,
+        };
 
-        Ok(Some(Filter {
-            column: column.to_string(),
+        let node = FilterNode::Condition(Filter {
+            column,
             operator,
             value: value.to_string(),
-        }))
+            aggregate,
+            path,
+        });
+
+        Ok(if negated {
+            FilterNode::Not(Box::new(node))
+        } else {
+            node
+        })
+    }
+
+    /// 解析操作符名称，全文检索操作符可带语言参数: fts(english)
+    fn parse_operator(op: &str) -> Result<Option<FilterOperator>> {
+        if let Some((name, rest)) = op.split_once('(') {
+            let language = rest.strip_suffix(')').ok_or_else(|| {
+                AppError::InvalidQuery(format!("无效的操作符: {}", op))
+            })?;
+            let language = Some(Self::sanitize_identifier(language)?);
+            let operator = match name {
+                "fts" => FilterOperator::Fts(language),
+                "plfts" => FilterOperator::Plfts(language),
+                "phfts" => FilterOperator::Phfts(language),
+                "wfts" => FilterOperator::Wfts(language),
+                _ => return Ok(None),
+            };
+            return Ok(Some(operator));
+        }
+
+        let operator = match op {
+            "eq" => FilterOperator::Eq,
+            "neq" => FilterOperator::Neq,
+            "gt" => FilterOperator::Gt,
+            "gte" => FilterOperator::Gte,
+            "lt" => FilterOperator::Lt,
+            "lte" => FilterOperator::Lte,
+            "like" => FilterOperator::Like,
+            "ilike" => FilterOperator::Ilike,
+            "in" => FilterOperator::In,
+            "is" => FilterOperator::Is,
+            "fts" => FilterOperator::Fts(None),
+            "plfts" => FilterOperator::Plfts(None),
+            "phfts" => FilterOperator::Phfts(None),
+            "wfts" => FilterOperator::Wfts(None),
+            "cs" => FilterOperator::Cs,
+            "cd" => FilterOperator::Cd,
+            "ov" => FilterOperator::Ov,
+            "sl" => FilterOperator::Sl,
+            "sr" => FilterOperator::Sr,
+            "nxl" => FilterOperator::Nxl,
+            "nxr" => FilterOperator::Nxr,
+            "adj" => FilterOperator::Adj,
+            "haskey" => FilterOperator::HasKey,
+            _ => return Ok(None),
+        };
+        Ok(Some(operator))
+    }
+
+    /// 解析逻辑组合参数（key 为 and / or / not.and / not.or），allow_aggregate 为 having 条件
+    fn parse_logic_group(key: &str, value: &str, allow_aggregate: bool) -> Result<FilterNode> {
+        let (negated, kind) = match key.strip_prefix("not.") {
+            Some(kind) => (true, kind),
+            None => (false, key),
+        };
+
+        let inner = value
+            .trim()
+            .strip_prefix('(')
+            .and_then(|v| v.strip_suffix(')'))
+            .ok_or_else(|| {
+                AppError::InvalidQuery(format!("{} 条件必须用括号包裹: {}", key, value))
+            })?;
+
+        let node = Self::build_group(kind, inner, allow_aggregate)?;
+
+        Ok(if negated {
+            FilterNode::Not(Box::new(node))
+        } else {
+            node
+        })
+    }
+
+    /// 构建 and / or 组合节点
+    fn build_group(kind: &str, inner: &str, allow_aggregate: bool) -> Result<FilterNode> {
+        let children = Self::split_top_level(inner)?
+            .iter()
+            .map(|item| Self::parse_group_item(item, allow_aggregate))
+            .collect::<Result<Vec<_>>>()?;
+
+        if children.is_empty() {
+            return Err(AppError::InvalidQuery(format!("{} 条件不能为空", kind)));
+        }
+
+        match kind {
+            "and" => Ok(FilterNode::And(children)),
+            "or" => Ok(FilterNode::Or(children)),
+            _ => Err(AppError::InvalidQuery(format!("不支持的逻辑操作: {}", kind))),
+        }
+    }
+
+    /// 解析组合内部的单项: and(...) / or(...) / not.xxx / column.op.value
+    fn parse_group_item(item: &str, allow_aggregate: bool) -> Result<FilterNode> {
+        let item = item.trim();
+
+        if let Some(rest) = item.strip_prefix("not.") {
+            return Ok(FilterNode::Not(Box::new(Self::parse_group_item(
+                rest,
+                allow_aggregate,
+            )?)));
+        }
+
+        for kind in ["and", "or"] {
+            if let Some(inner) = item
+                .strip_prefix(kind)
+                .and_then(|rest| rest.strip_prefix('('))
+                .and_then(|rest| rest.strip_suffix(')'))
+            {
+                return Self::build_group(kind, inner, allow_aggregate);
+            }
+        }
+
+        // column.op.value / column.not.op.value
+        let mut parts = item.splitn(2, '.');
+        let column = parts.next().unwrap_or_default();
+        let rest = parts.next().ok_or_else(|| {
+            AppError::InvalidQuery(format!("无效的组合条件: {}", item))
+        })?;
+
+        let (negated, rest) = match rest.strip_prefix("not.") {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+
+        let (op, value) = rest.split_once('.').ok_or_else(|| {
+            AppError::InvalidQuery(format!("组合条件缺少值: {}", item))
+        })?;
+
+        // 组合内部不能静默忽略条件，否则会改变 OR 的语义
+        let operator = Self::parse_operator(op)?
+            .ok_or_else(|| AppError::InvalidQuery(format!("不支持的操作符: {}", op)))?;
+
+        // having 中的条件必须作用于聚合函数: sum(amount).gt.1000
+        let (aggregate, column) = match Self::split_aggregate(column)? {
+            Some((function, column)) if allow_aggregate => (Some(function), column),
+            Some(_) => {
+                return Err(AppError::InvalidQuery(format!(
+                    "聚合函数只能用于 having 条件: {}",
+                    item
+                )))
+            }
+            None if allow_aggregate => {
+                return Err(AppError::InvalidQuery(format!(
+                    "having 条件必须使用聚合函数: {}",
+                    item
+                )))
+            }
+            None => (None, column.to_string()),
+        };
+
+        Self::condition(&column, aggregate, operator, &Self::unquote(value), negated)
+    }
+
+    /// 按顶层逗号切分（忽略括号、花括号和双引号内的逗号）
+    fn split_top_level(input: &str) -> Result<Vec<String>> {
+        let mut items = Vec::new();
+        let mut current = String::new();
+        let mut depth = 0usize;
+        let mut in_quotes = false;
+
+        for c in input.chars() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                '(' | '{' if !in_quotes => depth += 1,
+                ')' | '}' if !in_quotes => {
+                    depth = depth.checked_sub(1).ok_or_else(|| {
+                        AppError::InvalidQuery(format!("括号不匹配: {}", input))
+                    })?;
+                }
+                ',' if !in_quotes && depth == 0 => {
+                    items.push(std::mem::take(&mut current));
+                    continue;
+                }
+                _ => {}
+            }
+            current.push(c);
+        }
+
+        if depth != 0 || in_quotes {
+            return Err(AppError::InvalidQuery(format!("括号或引号不匹配: {}", input)));
+        }
+
+        if !current.trim().is_empty() || !items.is_empty() {
+            items.push(current);
+        }
+
+        Ok(items)
+    }
+
+    /// 去掉 IN 列表外层的括号: (a,b) -> a,b
+    fn unwrap_list(value: &str) -> &str {
+        value
+            .strip_prefix('(')
+            .and_then(|v| v.strip_suffix(')'))
+            .unwrap_or(value)
+    }
+
+    /// 去掉值两侧的双引号（用于包含逗号或括号的值）
+    fn unquote(value: &str) -> String {
+        if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            value[1..value.len() - 1].to_string()
+        } else {
+            value.to_string()
+        }
     }
 
     /// 解析排序
@@ -153,10 +980,22 @@ impl QueryParams {
                 (part, true) // 默认升序
             };
 
+            // rank(列)：按指定列上的全文检索条件计算相关度
+            if let Some(rank) = column.strip_prefix("rank(").and_then(|c| c.strip_suffix(')')) {
+                Self::sanitize_identifier(rank)?;
+                orders.push(OrderBy {
+                    column: "rank".to_string(),
+                    ascending,
+                    rank: Some(rank.to_string()),
+                });
+                continue;
+            }
+
             Self::sanitize_identifier(column)?;
             orders.push(OrderBy {
                 column: column.to_string(),
                 ascending,
+                rank: None,
             });
         }
 
@@ -196,6 +1035,10 @@ pub struct SqlBuilder {
     schema: String,
     table: String,
     params: QueryParams,
+    /// 写操作是否附带 RETURNING 子句
+    returning: bool,
+    /// INSERT 是否带 OVERRIDING SYSTEM VALUE（显式写入 GENERATED ALWAYS 标识列）
+    overriding_system_value: bool,
 }
 
 impl SqlBuilder {
@@ -208,157 +1051,952 @@ impl SqlBuilder {
             schema,
             table,
             params,
+            returning: true,
+            overriding_system_value: false,
         })
     }
 
-    /// 构建 SELECT 查询
-    pub fn build_select(&self) -> Result<(String, PgArguments)> {
+    /// 写操作不附带 RETURNING（Prefer: return=minimal）
+    pub fn without_returning(mut self) -> Self {
+        self.returning = false;
+        self
+    }
+
+    /// INSERT 显式写入 GENERATED ALWAYS 标识列（例如 PUT 按路径中的主键写入）
+    pub fn overriding_system_value(mut self) -> Self {
+        self.overriding_system_value = true;
+        self
+    }
+
+    /// 构建 SELECT 查询
+    pub fn build_select(&self) -> Result<(String, PgArguments)> {
         let mut args = PgArguments::default();
         let mut arg_index = 1;
+        let mut alias_seq = 0;
+
+        // SELECT 字段（嵌入资源以关联子查询的形式出现在字段列表中）
+        let parent = format!("\"{}\".\"{}\"", self.schema, self.table);
+        let select_clause = Self::compile_select(
+            &self.params.select,
+            &parent,
+            "",
+            &mut args,
+            &mut arg_index,
+            &mut alias_seq,
+        )?;
+
+        let mut sql = format!("SELECT {}", select_clause);
+
+        // 游标分页时以文本形式附带排序键，用于生成 next_cursor
+        if self.params.cursor.is_some() {
+            for (i, order) in self.params.order_by.iter().enumerate() {
+                sql.push_str(&format!(
+                    ", \"{}\"::text AS \"{}{}\"",
+                    order.column, CURSOR_COLUMN_PREFIX, i
+                ));
+            }
+        }
+
+        sql.push_str(&format!(" FROM {}", parent));
+
+        // WHERE 条件
+        let mut where_clause = self.compile_where(&mut args, &mut arg_index)?;
+        if let Some(keyset) = self.compile_keyset(&mut args, &mut arg_index)? {
+            where_clause.push_str(if where_clause.is_empty() { " WHERE " } else { " AND " });
+            where_clause.push_str(&keyset);
+        }
+        sql.push_str(&where_clause);
+
+        // GROUP BY / HAVING
+        sql.push_str(&Self::compile_grouping(&self.params, "", &mut args, &mut arg_index)?);
+
+        // ORDER BY / LIMIT / OFFSET
+        sql.push_str(&Self::compile_paging(&self.params, "", &mut args, &mut arg_index)?);
+
+        Ok((sql, args))
+    }
+
+    /// 构建逐行返回 PostgreSQL 生成的 JSON 文本的查询
+    pub fn build_select_json_rows(&self) -> Result<(String, PgArguments)> {
+        let (sql, args) = self.build_select()?;
+        Ok((
+            format!("SELECT row_to_json(\"_t\")::text FROM ({}) AS \"_t\"", sql),
+            args,
+        ))
+    }
+
+    /// 构建逐行返回 JSON 文本与本页行数的查询：COUNT(*) OVER () 在第一行返回前统计完整个结果
+    pub fn build_select_json_page(&self) -> Result<(String, PgArguments)> {
+        let (sql, args) = self.build_select()?;
+        Ok((
+            format!(
+                "SELECT row_to_json(\"_t\")::text, COUNT(*) OVER () FROM ({}) AS \"_t\"",
+                sql
+            ),
+            args,
+        ))
+    }
+
+    /// 构建 COUNT 查询（与 build_select 使用相同的过滤条件，忽略分页）
+    pub fn build_count(&self) -> Result<(String, PgArguments)> {
+        self.build_filtered("COUNT(*)")
+    }
+
+    /// 构建 EXPLAIN 查询，用于读取计划器估算的行数
+    pub fn build_count_plan(&self) -> Result<(String, PgArguments)> {
+        let (sql, args) = self.build_filtered("1")?;
+        Ok((format!("EXPLAIN (FORMAT JSON) {}", sql), args))
+    }
+
+    /// 构建检查结果是否多于 rows 行的查询（从 offset 起算，忽略 limit），
+    /// 流式响应据此在输出第一个字节前拒绝超出上限的请求
+    pub fn build_exceeds(&self, rows: i64) -> Result<(String, PgArguments)> {
+        let (sql, args) = self.build_filtered("1")?;
+        let skip = self.params.offset.unwrap_or(0) + rows;
+        Ok((format!("SELECT EXISTS ({} OFFSET {})", sql, skip), args))
+    }
+
+    /// 构建只带 WHERE 条件的 SELECT（聚合查询时统计分组数）
+    fn build_filtered(&self, select_clause: &str) -> Result<(String, PgArguments)> {
+        let mut args = PgArguments::default();
+        let mut arg_index = 1;
+
+        let mut from = format!("\"{}\".\"{}\"", self.schema, self.table);
+        let where_clause = self.compile_where(&mut args, &mut arg_index)?;
+
+        if self.params.is_grouped() {
+            let grouping = Self::compile_grouping(&self.params, "", &mut args, &mut arg_index)?;
+            from = format!("(SELECT 1 FROM {}{}{}) AS \"_g\"", from, where_clause, grouping);
+            return Ok((format!("SELECT {} FROM {}", select_clause, from), args));
+        }
+
+        Ok((format!("SELECT {} FROM {}{}", select_clause, from, where_clause), args))
+    }
+
+    /// 编译游标条件: ("a", "b") > (CAST($1 AS type), CAST($2 AS type))
+    fn compile_keyset(&self, args: &mut PgArguments, arg_index: &mut usize) -> Result<Option<String>> {
+        let cursor = match &self.params.cursor {
+            Some(cursor) if !cursor.values.is_empty() => cursor,
+            _ => return Ok(None),
+        };
+
+        let order_by = &self.params.order_by;
+        if cursor.values.len() != order_by.len() {
+            return Err(AppError::InvalidQuery("cursor 与当前排序不匹配".to_string()));
+        }
+
+        let columns: Vec<String> = order_by
+            .iter()
+            .map(|order| format!("\"{}\"", order.column))
+            .collect();
+
+        // 游标值是服务端以 ::text 输出的，按列类型原样转换回去
+        let values = cursor
+            .values
+            .iter()
+            .zip(order_by)
+            .map(|(value, order)| {
+                let column_type = self.params.column_types.get(&order.column).ok_or_else(|| {
+                    AppError::InvalidQuery(format!("列不存在: {}", order.column))
+                })?;
+                args.add(value);
+                let placeholder = format!("CAST(${} AS {})", arg_index, column_type);
+                *arg_index += 1;
+                Ok(placeholder)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let ascending = order_by.first().is_none_or(|order| order.ascending);
+
+        Ok(Some(format!(
+            "({}) {} ({})",
+            columns.join(", "),
+            if ascending { ">" } else { "<" },
+            values.join(", ")
+        )))
+    }
+
+    /// 编译顶层 WHERE 子句（无条件时返回空字符串）
+    fn compile_where(&self, args: &mut PgArguments, arg_index: &mut usize) -> Result<String> {
+        if self.params.filters.is_empty() {
+            return Ok(String::new());
+        }
+
+        let conditions = self
+            .params
+            .filters
+            .iter()
+            .map(|node| Self::compile_node(node, &self.params.column_types, "", args, arg_index))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(format!(" WHERE {}", conditions.join(" AND ")))
+    }
 
-        // SELECT 字段
-        let select_clause = match &self.params.select {
-            Some(fields) => fields.join(", "),
-            None => "*".to_string(),
+    /// 编译 SELECT 字段列表，parent 为父表在 SQL 中的引用
+    fn compile_select(
+        select: &Option<Vec<SelectItem>>,
+        parent: &str,
+        qualifier: &str,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+        alias_seq: &mut usize,
+    ) -> Result<String> {
+        let items = match select {
+            Some(items) if !items.is_empty() => items,
+            _ => return Ok("*".to_string()),
         };
 
+        let fields = items
+            .iter()
+            .map(|item| match item {
+                SelectItem::Column(column) if column == "*" => Ok("*".to_string()),
+                SelectItem::Column(column) => Ok(format!("{}\"{}\"", qualifier, column)),
+                SelectItem::Embed(embed) => {
+                    Self::compile_embed(embed, parent, args, arg_index, alias_seq)
+                }
+                SelectItem::Aggregate(aggregate) => Ok(format!(
+                    "{} AS \"{}\"",
+                    aggregate.function.to_sql(qualifier, &aggregate.column),
+                    aggregate.alias
+                )),
+                SelectItem::JsonPath(item) => Ok(format!(
+                    "{} AS \"{}\"",
+                    Self::compile_json_path(qualifier, &item.column, &item.path, args, arg_index),
+                    item.alias
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(fields.join(", "))
+    }
+
+    /// 编译嵌入资源为关联子查询：多对一返回 row_to_json，一对多返回 json_agg
+    fn compile_embed(
+        embed: &Embed,
+        parent: &str,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+        alias_seq: &mut usize,
+    ) -> Result<String> {
+        let join = embed.join.as_ref().ok_or_else(|| {
+            AppError::Internal(format!("嵌入资源 {} 的关联关系尚未解析", embed.table))
+        })?;
+        Self::check_embed_columns(embed)?;
+
+        *alias_seq += 1;
+        let seq = *alias_seq;
+        let alias = format!("\"_e{}\"", seq);
+
+        // 嵌入子查询中的列一律带上别名，避免被解析为父表列的关联引用
+        let qualifier = format!("{}.", alias);
+        let select_clause = Self::compile_select(
+            &embed.params.select,
+            &alias,
+            &qualifier,
+            args,
+            arg_index,
+            alias_seq,
+        )?;
+
+        let mut conditions: Vec<String> = join
+            .columns
+            .iter()
+            .map(|(column, parent_column)| {
+                format!("{}.\"{}\" = {}.\"{}\"", alias, column, parent, parent_column)
+            })
+            .collect();
+        for node in &embed.params.filters {
+            conditions.push(Self::compile_node(
+                node,
+                &embed.params.column_types,
+                &qualifier,
+                args,
+                arg_index,
+            )?);
+        }
+
         let mut sql = format!(
-            "SELECT {} FROM \"{}\".\"{}\"",
-            select_clause, self.schema, self.table
+            "SELECT {} FROM \"{}\".\"{}\" AS {} WHERE {}",
+            select_clause,
+            join.schema,
+            embed.table,
+            alias,
+            conditions.join(" AND ")
         );
 
-        // WHERE 条件
-        if !self.params.filters.is_empty() {
-            sql.push_str(" WHERE ");
-            let conditions: Vec<String> = self
-                .params
-                .filters
+        sql.push_str(&Self::compile_grouping(&embed.params, &qualifier, args, arg_index)?);
+
+        if join.to_many {
+            sql.push_str(&Self::compile_paging(&embed.params, &qualifier, args, arg_index)?);
+            Ok(format!(
+                "(SELECT coalesce(json_agg(\"_r{seq}\"), '[]'::json) FROM ({sql}) AS \"_r{seq}\") AS \"{}\"",
+                embed.table
+            ))
+        } else {
+            Ok(format!(
+                "(SELECT row_to_json(\"_r{seq}\") FROM ({sql} LIMIT 1) AS \"_r{seq}\") AS \"{}\"",
+                embed.table
+            ))
+        }
+    }
+
+    /// 确认嵌入资源的 select / 过滤 / 排序引用的列都属于嵌入表，列信息未知时拒绝查询
+    fn check_embed_columns(embed: &Embed) -> Result<()> {
+        fn collect<'a>(nodes: &'a [FilterNode], columns: &mut Vec<&'a str>) {
+            for node in nodes {
+                match node {
+                    FilterNode::Condition(filter) => columns.push(&filter.column),
+                    FilterNode::And(children) | FilterNode::Or(children) => {
+                        collect(children, columns)
+                    }
+                    FilterNode::Not(child) => collect(std::slice::from_ref(child.as_ref()), columns),
+                }
+            }
+        }
+
+        let params = &embed.params;
+        if params.column_types.is_empty() {
+            return Err(AppError::InvalidQuery(format!(
+                "嵌入资源 {} 的列信息未知，无法校验查询中的列",
+                embed.table
+            )));
+        }
+
+        let mut columns: Vec<&str> = Vec::new();
+        for item in params.select.iter().flatten() {
+            match item {
+                SelectItem::Column(column) => columns.push(column),
+                SelectItem::Aggregate(Aggregate { column, .. })
+                | SelectItem::JsonPath(JsonPathSelect { column, .. }) => columns.push(column),
+                SelectItem::Embed(_) => {}
+            }
+        }
+        collect(&params.filters, &mut columns);
+        collect(&params.having, &mut columns);
+
+        // 排序可以引用 select 中的输出字段名；rank 由 compile_paging 对照全文检索条件检查
+        let aliases = params.output_aliases();
+        columns.extend(
+            params
+                .order_by
                 .iter()
-                .map(|filter| {
-                    let condition = match filter.operator {
-                        FilterOperator::Eq => {
-                            args.add(&filter.value);
-                            format!("\"{}\" = ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::Neq => {
-                            args.add(&filter.value);
-                            format!("\"{}\" != ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::Gt => {
-                            args.add(&filter.value);
-                            format!("\"{}\" > ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::Gte => {
-                            args.add(&filter.value);
-                            format!("\"{}\" >= ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::Lt => {
-                            args.add(&filter.value);
-                            format!("\"{}\" < ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::Lte => {
-                            args.add(&filter.value);
-                            format!("\"{}\" <= ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::Like => {
-                            args.add(&filter.value);
-                            format!("\"{}\" LIKE ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::Ilike => {
-                            args.add(&filter.value);
-                            format!("\"{}\" ILIKE ${}", filter.column, arg_index)
-                        }
-                        FilterOperator::In => {
-                            let values: Vec<&str> = filter.value.split(',').collect();
-                            let placeholders: Vec<String> = values
-                                .iter()
-                                .map(|v| {
-                                    args.add(*v);
-                                    let placeholder = format!("${}", arg_index);
-                                    arg_index += 1;
-                                    placeholder
-                                })
-                                .collect();
-                            arg_index -= 1; // 调整因为循环会再加一次
-                            format!("\"{}\" IN ({})", filter.column, placeholders.join(", "))
-                        }
-                        FilterOperator::Is => {
-                            if filter.value.to_lowercase() == "null" {
-                                format!("\"{}\" IS NULL", filter.column)
-                            } else {
-                                format!("\"{}\" IS NOT NULL", filter.column)
-                            }
-                        }
-                    };
-                    arg_index += 1;
-                    condition
-                })
-                .collect();
+                .map(|order| order.column.as_str())
+                .filter(|column| !aliases.contains(column) && *column != "rank"),
+        );
+
+        match columns
+            .into_iter()
+            .find(|column| *column != "*" && !params.column_types.contains_key(*column))
+        {
+            Some(column) => Err(AppError::InvalidQuery(format!(
+                "嵌入资源 {} 中没有列 {}",
+                embed.table, column
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// 编译 GROUP BY / HAVING：聚合查询按 select 中的普通列分组
+    fn compile_grouping(
+        params: &QueryParams,
+        qualifier: &str,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+    ) -> Result<String> {
+        let mut sql = String::new();
+
+        if !params.is_grouped() {
+            return Ok(sql);
+        }
+
+        // JSON 路径的路径段是参数，无法在 GROUP BY 中重复书写，改用 select 中的序号
+        let columns: Vec<String> = params
+            .select
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(i, item)| match item {
+                SelectItem::Column(column) => Some(format!("{}\"{}\"", qualifier, column)),
+                SelectItem::JsonPath(_) => Some((i + 1).to_string()),
+                _ => None,
+            })
+            .collect();
+        if !columns.is_empty() {
+            sql.push_str(" GROUP BY ");
+            sql.push_str(&columns.join(", "));
+        }
+
+        if !params.having.is_empty() {
+            let conditions: Vec<String> = params
+                .having
+                .iter()
+                .map(|node| Self::compile_node(node, &params.column_types, qualifier, args, arg_index))
+                .collect::<Result<Vec<_>>>()?;
+            sql.push_str(" HAVING ");
             sql.push_str(&conditions.join(" AND "));
         }
 
-        // ORDER BY
-        if !self.params.order_by.is_empty() {
-            sql.push_str(" ORDER BY ");
-            let orders: Vec<String> = self
-                .params
+        Ok(sql)
+    }
+
+    /// 编译 ORDER BY / LIMIT / OFFSET
+    fn compile_paging(
+        params: &QueryParams,
+        qualifier: &str,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+    ) -> Result<String> {
+        let mut sql = String::new();
+
+        if !params.order_by.is_empty() {
+            let aliases = params.output_aliases();
+            let orders: Vec<String> = params
                 .order_by
                 .iter()
                 .map(|order| {
-                    format!(
-                        "\"{}\" {}",
-                        order.column,
-                        if order.ascending { "ASC" } else { "DESC" }
-                    )
+                    let direction = if order.ascending { "ASC" } else { "DESC" };
+
+                    // order=rank.desc：按全文检索相关度排序
+                    if let Some(filter) = Self::rank_filter(params, order)? {
+                        let column = Self::compile_json_path(
+                            qualifier,
+                            &filter.column,
+                            &filter.path,
+                            args,
+                            arg_index,
+                        );
+                        let (vector, query) = Self::compile_text_search(
+                            filter,
+                            column,
+                            &params.column_types,
+                            args,
+                            arg_index,
+                        );
+                        return Ok(format!("ts_rank({}, {}) {}", vector, query, direction));
+                    }
+
+                    // select 中的输出字段名（聚合、JSON 路径的别名）不属于表，不加前缀
+                    let qualifier = if aliases.contains(&order.column.as_str()) { "" } else { qualifier };
+                    Ok(format!("{}\"{}\" {}", qualifier, order.column, direction))
                 })
-                .collect();
+                .collect::<Result<Vec<_>>>()?;
+            sql.push_str(" ORDER BY ");
             sql.push_str(&orders.join(", "));
         }
 
-        // LIMIT
-        if let Some(limit) = self.params.limit {
+        if let Some(limit) = params.limit {
             args.add(limit);
             sql.push_str(&format!(" LIMIT ${}", arg_index));
-            arg_index += 1;
+            *arg_index += 1;
         }
 
-        // OFFSET
-        if let Some(offset) = self.params.offset {
+        if let Some(offset) = params.offset {
             args.add(offset);
             sql.push_str(&format!(" OFFSET ${}", arg_index));
+            *arg_index += 1;
         }
 
-        Ok((sql, args))
+        Ok(sql)
     }
 
-    /// 构建 INSERT 查询
-    pub fn build_insert(&self, data: &serde_json::Value) -> Result<(String, PgArguments)> {
-        let mut args = PgArguments::default();
+    /// 编译过滤条件树为 SQL 片段，参数按列类型依次追加到 args
+    fn compile_node(
+        node: &FilterNode,
+        types: &HashMap<String, String>,
+        qualifier: &str,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+    ) -> Result<String> {
+        match node {
+            FilterNode::Condition(filter) => {
+                Self::compile_condition(filter, types, qualifier, args, arg_index)
+            }
+            FilterNode::And(children) => {
+                let parts = children
+                    .iter()
+                    .map(|child| Self::compile_node(child, types, qualifier, args, arg_index))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!("({})", parts.join(" AND ")))
+            }
+            FilterNode::Or(children) => {
+                let parts = children
+                    .iter()
+                    .map(|child| Self::compile_node(child, types, qualifier, args, arg_index))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!("({})", parts.join(" OR ")))
+            }
+            FilterNode::Not(child) => Ok(format!(
+                "NOT ({})",
+                Self::compile_node(child, types, qualifier, args, arg_index)?
+            )),
+        }
+    }
 
-        let obj = data
-            .as_object()
-            .ok_or_else(|| AppError::InvalidQuery("期望 JSON 对象".to_string()))?;
+    /// 编译全文检索的 (tsvector, tsquery) 表达式：tsvector 列直接使用，其他列经 to_tsvector 转换
+    ///
+    /// 语言配置已通过标识符校验，直接作为字面量写入，以便命中 to_tsvector('english', col) 表达式索引
+    fn compile_text_search(
+        filter: &Filter,
+        column: String,
+        types: &HashMap<String, String>,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+    ) -> (String, String) {
+        let (function, language) = filter.operator.text_search().unwrap_or(("to_tsquery", None));
+        let config = language
+            .map(|language| format!("'{}', ", language))
+            .unwrap_or_default();
 
-        if obj.is_empty() {
-            return Err(AppError::InvalidQuery("插入数据不能为空".to_string()));
+        let is_tsvector = filter.path.is_empty()
+            && types.get(&filter.column).map(String::as_str) == Some("tsvector");
+        let vector = if is_tsvector {
+            column
+        } else {
+            format!("to_tsvector({}{})", config, column)
+        };
+
+        args.add(&filter.value);
+        let query = format!("{}({}${})", function, config, arg_index);
+        *arg_index += 1;
+
+        (vector, query)
+    }
+
+    /// 编译列引用，带 JSON 路径时为 ("col"->$1->>$2)，路径段以参数绑定（纯数字按数组下标绑定）
+    fn compile_json_path(
+        qualifier: &str,
+        column: &str,
+        path: &[JsonPathSegment],
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+    ) -> String {
+        if path.is_empty() {
+            return format!("{}\"{}\"", qualifier, column);
         }
 
-        let mut columns = Vec::new();
-        let mut placeholders = Vec::new();
+        let mut sql = format!("({}\"{}\"", qualifier, column);
+        for segment in path {
+            match segment.key.parse::<i32>() {
+                Ok(index) => args.add(index),
+                Err(_) => args.add(&segment.key),
+            }
+            let arrow = if segment.as_text { "->>" } else { "->" };
+            sql.push_str(&format!("{}${}", arrow, arg_index));
+            *arg_index += 1;
+        }
+        sql.push(')');
+        sql
+    }
 
-        for (i, (key, value)) in obj.iter().enumerate() {
-            QueryParams::sanitize_identifier(key)?;
-            columns.push(format!("\"{}\"", key));
-            placeholders.push(format!("${}", i + 1));
-            args.add(value);
+    /// 确定按相关度排序时使用的全文检索条件：order=rank 要求恰好一个全文检索条件，
+    /// order=rank(列) 要求该列上恰好一个；表中有 rank 列时 order=rank 按普通列排序
+    fn rank_filter<'a>(params: &'a QueryParams, order: &OrderBy) -> Result<Option<&'a Filter>> {
+        if order.rank.is_none()
+            && (order.column != "rank" || params.column_types.contains_key("rank"))
+        {
+            return Ok(None);
+        }
+
+        let mut filters = Vec::new();
+        Self::collect_text_search(&params.filters, &mut filters);
+        if let Some(column) = &order.rank {
+            filters.retain(|filter| &filter.column == column);
+        }
+
+        match (filters.as_slice(), &order.rank) {
+            ([filter], _) => Ok(Some(filter)),
+            ([], None) => Err(AppError::InvalidQuery(
+                "order=rank 需要一个全文检索条件（fts / plfts / phfts / wfts）".to_string(),
+            )),
+            ([], Some(column)) => Err(AppError::InvalidQuery(format!(
+                "order=rank({}) 需要列 {} 上的全文检索条件",
+                column, column
+            ))),
+            (_, None) => Err(AppError::InvalidQuery(
+                "存在多个全文检索条件，请用 order=rank(列名) 指定按哪一列计算相关度".to_string(),
+            )),
+            (_, Some(column)) => Err(AppError::InvalidQuery(format!(
+                "列 {} 上有多个全文检索条件，无法确定相关度的计算方式",
+                column
+            ))),
+        }
+    }
+
+    /// 收集全文检索条件（用于 order=rank 排序），忽略 NOT 内部的条件
+    fn collect_text_search<'a>(nodes: &'a [FilterNode], filters: &mut Vec<&'a Filter>) {
+        for node in nodes {
+            match node {
+                FilterNode::Condition(filter) if filter.operator.text_search().is_some() => {
+                    filters.push(filter)
+                }
+                FilterNode::And(children) | FilterNode::Or(children) => {
+                    Self::collect_text_search(children, filters)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 编译单个过滤条件
+    fn compile_condition(
+        filter: &Filter,
+        types: &HashMap<String, String>,
+        qualifier: &str,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+    ) -> Result<String> {
+        let column_type = types.get(&filter.column).map(String::as_str);
+
+        // JSON 路径：->> 的结果按文本比较，-> 的结果与列同为 json / jsonb
+        let column_type = match filter.path.last() {
+            None => column_type,
+            Some(_) if column_type.is_some_and(|t| !matches!(t, "json" | "jsonb")) => {
+                return Err(AppError::InvalidQuery(format!(
+                    "列 {} 不是 JSON 类型，不能使用 JSON 路径",
+                    filter.column
+                )));
+            }
+            Some(segment) if segment.as_text => Some("text"),
+            Some(_) => Some(if column_type == Some("json") { "json" } else { "jsonb" }),
+        };
+
+        // having 条件作用于聚合表达式；count / sum / avg 的结果按 numeric 比较，min / max 与列同类型
+        let (column, value_type) = match filter.aggregate {
+            Some(function) if function.is_numeric() => {
+                (function.to_sql(qualifier, &filter.column), Some("numeric"))
+            }
+            Some(function) => (function.to_sql(qualifier, &filter.column), column_type),
+            None => (
+                Self::compile_json_path(qualifier, &filter.column, &filter.path, args, arg_index),
+                column_type,
+            ),
+        };
+
+        let sql_operator = match filter.operator {
+            FilterOperator::Fts(_)
+            | FilterOperator::Plfts(_)
+            | FilterOperator::Phfts(_)
+            | FilterOperator::Wfts(_) => {
+                if filter.aggregate.is_some() {
+                    return Err(AppError::InvalidQuery(
+                        "全文检索不能用于 having 条件".to_string(),
+                    ));
+                }
+                let (vector, query) =
+                    Self::compile_text_search(filter, column, types, args, arg_index);
+                return Ok(format!("{} @@ {}", vector, query));
+            }
+            FilterOperator::HasKey => {
+                args.add(&filter.value);
+                let condition = format!("{} ? ${}", column, arg_index);
+                *arg_index += 1;
+                return Ok(condition);
+            }
+            FilterOperator::Cs => {
+                // 范围包含单个元素时按元素类型绑定: during.cs=2024-01-15
+                let element_type = value_type
+                    .and_then(range_subtype)
+                    .filter(|_| !is_range_literal(&filter.value));
+                let placeholder = bind_typed(
+                    args,
+                    arg_index,
+                    &filter.column,
+                    Some(&filter.value),
+                    element_type.or(value_type),
+                )?;
+                return Ok(format!("{} @> {}", column, placeholder));
+            }
+            FilterOperator::Cd => "<@",
+            FilterOperator::Ov => "&&",
+            FilterOperator::Sl => "<<",
+            FilterOperator::Sr => ">>",
+            FilterOperator::Nxl => "&<",
+            FilterOperator::Nxr => "&>",
+            FilterOperator::Adj => "-|-",
+            FilterOperator::Eq => "=",
+            FilterOperator::Neq => "!=",
+            FilterOperator::Gt => ">",
+            FilterOperator::Gte => ">=",
+            FilterOperator::Lt => "<",
+            FilterOperator::Lte => "<=",
+            FilterOperator::Like | FilterOperator::Ilike => {
+                // 模式匹配总是按文本进行，非文本列先转换为 text
+                let column = match value_type {
+                    Some(value_type) if !is_text_type(value_type) => format!("{}::text", column),
+                    _ => column,
+                };
+                let keyword = match filter.operator {
+                    FilterOperator::Like => "LIKE",
+                    _ => "ILIKE",
+                };
+                args.add(&filter.value);
+                let condition = format!("{} {} ${}", column, keyword, arg_index);
+                *arg_index += 1;
+                return Ok(condition);
+            }
+            FilterOperator::In => {
+                let placeholders = filter
+                    .value
+                    .split(',')
+                    .map(|value| {
+                        bind_typed(args, arg_index, &filter.column, Some(value), value_type)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                return Ok(format!("{} IN ({})", column, placeholders.join(", ")));
+            }
+            FilterOperator::Is => {
+                return Ok(if filter.value.to_lowercase() == "null" {
+                    format!("{} IS NULL", column)
+                } else {
+                    format!("{} IS NOT NULL", column)
+                });
+            }
+        };
+
+        let placeholder =
+            bind_typed(args, arg_index, &filter.column, Some(&filter.value), value_type)?;
+        Ok(format!("{} {} {}", column, sql_operator, placeholder))
+    }
+
+    /// 按列类型绑定 INSERT / UPDATE 中的 JSON 值；列类型未知时按 JSON 绑定
+    fn bind_json(
+        &self,
+        args: &mut PgArguments,
+        arg_index: &mut usize,
+        column: &str,
+        value: &serde_json::Value,
+    ) -> Result<String> {
+        match self.params.column_types.get(column) {
+            Some(column_type) => {
+                let text = json_to_text(value, column_type);
+                bind_typed(args, arg_index, column, text.as_deref(), Some(column_type))
+            }
+            None => {
+                args.add(value);
+                let placeholder = format!("${}", arg_index);
+                *arg_index += 1;
+                Ok(placeholder)
+            }
+        }
+    }
+
+    /// 编译写操作的 RETURNING 子句，字段由 select 指定（默认 *），支持列、JSON 路径和嵌入资源
+    fn compile_returning(&self, args: &mut PgArguments, arg_index: &mut usize) -> Result<String> {
+        if !self.returning {
+            return Ok(String::new());
+        }
+
+        if self.params.is_grouped() {
+            return Err(AppError::InvalidQuery(
+                "写操作的 select 不能包含聚合函数".to_string(),
+            ));
+        }
+
+        let parent = format!("\"{}\".\"{}\"", self.schema, self.table);
+        let fields =
+            Self::compile_select(&self.params.select, &parent, "", args, arg_index, &mut 0)?;
+
+        Ok(format!(" RETURNING {}", fields))
+    }
+
+    /// 构建 INSERT 查询，data 为单个对象或对象数组
+    ///
+    /// 多行数据生成多行 VALUES，并按参数上限拆分为多条语句，调用方需在同一事务中依次执行。
+    /// 列集合由 columns 参数指定（缺失的键取列默认值）；未指定时各行的键必须与第一行相同。
+    pub fn build_insert(&self, data: &serde_json::Value) -> Result<Vec<(String, PgArguments)>> {
+        self.build_insert_statements(data, None)
+    }
+
+    /// 构建 upsert：INSERT ... ON CONFLICT (冲突列) DO UPDATE SET ... / DO NOTHING
+    ///
+    /// 冲突列取自 on_conflict 参数；合并时更新冲突列以外的所有插入列
+    pub fn build_upsert(
+        &self,
+        data: &serde_json::Value,
+        resolution: Resolution,
+    ) -> Result<Vec<(String, PgArguments)>> {
+        self.build_insert_statements(data, Some(resolution))
+    }
+
+    fn build_insert_statements(
+        &self,
+        data: &serde_json::Value,
+        resolution: Option<Resolution>,
+    ) -> Result<Vec<(String, PgArguments)>> {
+        let rows = match data {
+            serde_json::Value::Object(obj) => vec![obj],
+            serde_json::Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    item.as_object().ok_or_else(|| {
+                        AppError::InvalidQuery(format!("第 {} 条数据不是 JSON 对象", i + 1))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => {
+                return Err(AppError::InvalidQuery(
+                    "期望 JSON 对象或对象数组".to_string(),
+                ))
+            }
+        };
+
+        let columns = self.insert_columns(&rows)?;
+        let on_conflict = match resolution {
+            Some(resolution) => self.compile_on_conflict(&columns, resolution)?,
+            None => String::new(),
+        };
+        // RETURNING 中的 JSON 路径同样占用参数，每条语句都要为其预留
+        let mut arg_index = 1;
+        self.compile_returning(&mut PgArguments::default(), &mut arg_index)?;
+        let chunk_rows = (MAX_BIND_PARAMS.saturating_sub(arg_index - 1) / columns.len())
+            .clamp(1, INSERT_CHUNK_ROWS);
+
+        rows.chunks(chunk_rows)
+            .enumerate()
+            .map(|(i, chunk)| {
+                if matches!(resolution, Some(Resolution::MergeDuplicates)) {
+                    self.check_conflict_keys(chunk, i * chunk_rows)?;
+                }
+                self.build_insert_chunk(&columns, chunk, &on_conflict)
+            })
+            .collect()
+    }
+
+    /// 同一条 ON CONFLICT DO UPDATE 语句不能两次更新同一行（SQLSTATE 21000），
+    /// 因此拒绝冲突列取值相同的行；含 NULL 或缺省冲突列的行不会冲突，不参与比较
+    fn check_conflict_keys(
+        &self,
+        chunk: &[&serde_json::Map<String, serde_json::Value>],
+        offset: usize,
+    ) -> Result<()> {
+        let target = self.params.on_conflict.as_deref().unwrap_or_default();
+        let mut seen: HashMap<Vec<String>, usize> = HashMap::new();
+
+        for (i, row) in chunk.iter().enumerate() {
+            let key: Option<Vec<String>> = target
+                .iter()
+                .map(|column| {
+                    let value = row.get(column)?;
+                    match self.params.column_types.get(column) {
+                        Some(column_type) => json_to_text(value, column_type),
+                        None if value.is_null() => None,
+                        None => Some(value.to_string()),
+                    }
+                })
+                .collect();
+            let Some(key) = key else { continue };
+
+            if let Some(first) = seen.insert(key, offset + i + 1) {
+                return Err(AppError::InvalidQuery(format!(
+                    "第 {} 条数据与第 {} 条数据的冲突列（{}）取值相同，合并写入时每个冲突键只能出现一次",
+                    offset + i + 1,
+                    first,
+                    target.join(", ")
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 编译 ON CONFLICT 子句
+    fn compile_on_conflict(&self, columns: &[String], resolution: Resolution) -> Result<String> {
+        let target = match &self.params.on_conflict {
+            Some(target) if !target.is_empty() => target,
+            _ => {
+                return Err(AppError::InvalidQuery(
+                    "upsert 需要指定冲突列（on_conflict）".to_string(),
+                ))
+            }
+        };
+
+        let quoted: Vec<String> = target.iter().map(|column| format!("\"{}\"", column)).collect();
+        let action = match resolution {
+            Resolution::IgnoreDuplicates => "DO NOTHING".to_string(),
+            Resolution::MergeDuplicates => {
+                // 只插入了冲突列时仍写回冲突列本身，保证 RETURNING 返回已有行
+                let mut updates: Vec<&String> =
+                    columns.iter().filter(|column| !target.contains(column)).collect();
+                if updates.is_empty() {
+                    updates = target.iter().collect();
+                }
+                let assignments: Vec<String> = updates
+                    .iter()
+                    .map(|column| format!("\"{0}\" = EXCLUDED.\"{0}\"", column))
+                    .collect();
+                format!("DO UPDATE SET {}", assignments.join(", "))
+            }
+        };
+
+        Ok(format!(" ON CONFLICT ({}) {}", quoted.join(", "), action))
+    }
+
+    /// 确定 INSERT 的列集合
+    fn insert_columns(
+        &self,
+        rows: &[&serde_json::Map<String, serde_json::Value>],
+    ) -> Result<Vec<String>> {
+        let first = match rows.first() {
+            Some(first) if !first.is_empty() || self.params.columns.is_some() => first,
+            _ => return Err(AppError::InvalidQuery("插入数据不能为空".to_string())),
+        };
+
+        if let Some(columns) = &self.params.columns {
+            return Ok(columns.clone());
+        }
+
+        for (i, row) in rows.iter().enumerate().skip(1) {
+            if row.len() != first.len() || !row.keys().all(|key| first.contains_key(key)) {
+                return Err(AppError::InvalidQuery(format!(
+                    "第 {} 条数据的字段与第 1 条不一致，请使用 columns 参数指定列",
+                    i + 1
+                )));
+            }
         }
 
+        first
+            .keys()
+            .map(|key| QueryParams::sanitize_identifier(key))
+            .collect()
+    }
+
+    /// 构建单条多行 INSERT，缺失的列写 DEFAULT
+    fn build_insert_chunk(
+        &self,
+        columns: &[String],
+        rows: &[&serde_json::Map<String, serde_json::Value>],
+        on_conflict: &str,
+    ) -> Result<(String, PgArguments)> {
+        let mut args = PgArguments::default();
+        let mut arg_index = 1;
+
+        let mut values = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut placeholders = Vec::with_capacity(columns.len());
+            for column in columns {
+                placeholders.push(match row.get(column) {
+                    Some(value) => self.bind_json(&mut args, &mut arg_index, column, value)?,
+                    None => "DEFAULT".to_string(),
+                });
+            }
+            values.push(format!("({})", placeholders.join(", ")));
+        }
+
+        let columns: Vec<String> = columns
+            .iter()
+            .map(|column| format!("\"{}\"", column))
+            .collect();
+
         let sql = format!(
-            "INSERT INTO \"{}\".\"{}\" ({}) VALUES ({}) RETURNING *",
+            "INSERT INTO \"{}\".\"{}\" ({}){} VALUES {}{}{}",
             self.schema,
             self.table,
             columns.join(", "),
-            placeholders.join(", ")
+            if self.overriding_system_value { " OVERRIDING SYSTEM VALUE" } else { "" },
+            values.join(", "),
+            on_conflict,
+            self.compile_returning(&mut args, &mut arg_index)?
         );
 
         Ok((sql, args))
@@ -380,9 +2018,8 @@ impl SqlBuilder {
         let mut set_clauses = Vec::new();
         for (key, value) in obj.iter() {
             QueryParams::sanitize_identifier(key)?;
-            set_clauses.push(format!("\"{}\" = ${}", key, arg_index));
-            args.add(value);
-            arg_index += 1;
+            let placeholder = self.bind_json(&mut args, &mut arg_index, key, value)?;
+            set_clauses.push(format!("\"{}\" = {}", key, placeholder));
         }
 
         let mut sql = format!(
@@ -392,32 +2029,10 @@ impl SqlBuilder {
             set_clauses.join(", ")
         );
 
-        // WHERE 条件
-        if !self.params.filters.is_empty() {
-            sql.push_str(" WHERE ");
-            let conditions: Vec<String> = self
-                .params
-                .filters
-                .iter()
-                .map(|filter| {
-                    let condition = match filter.operator {
-                        FilterOperator::Eq => {
-                            args.add(&filter.value);
-                            format!("\"{}\" = ${}", filter.column, arg_index)
-                        }
-                        _ => {
-                            args.add(&filter.value);
-                            format!("\"{}\" = ${}", filter.column, arg_index)
-                        }
-                    };
-                    arg_index += 1;
-                    condition
-                })
-                .collect();
-            sql.push_str(&conditions.join(" AND "));
-        }
+        // WHERE 条件，与查询共用同一套编译逻辑
+        sql.push_str(&self.compile_where(&mut args, &mut arg_index)?);
 
-        sql.push_str(" RETURNING *");
+        sql.push_str(&self.compile_returning(&mut args, &mut arg_index)?);
 
         Ok((sql, args))
     }
@@ -435,24 +2050,788 @@ impl SqlBuilder {
                 "DELETE 操作必须提供 WHERE 条件".to_string(),
             ));
         }
+        sql.push_str(&self.compile_where(&mut args, &mut arg_index)?);
 
-        sql.push_str(" WHERE ");
-        let conditions: Vec<String> = self
-            .params
-            .filters
+        sql.push_str(&self.compile_returning(&mut args, &mut arg_index)?);
+
+        Ok((sql, args))
+    }
+}
+
+
+/// 按列类型绑定参数并返回占位符
+///
+/// 整数 / 浮点 / 布尔以对应的 Rust 类型绑定，文本类型直接绑定，其余类型以文本绑定并显式 CAST。
+/// 值无法按列类型解析时返回指明列名的 400 错误；列类型未知时按文本绑定。
+fn bind_typed(
+    args: &mut PgArguments,
+    arg_index: &mut usize,
+    column: &str,
+    value: Option<&str>,
+    column_type: Option<&str>,
+) -> Result<String> {
+    let placeholder = format!("${}", arg_index);
+    *arg_index += 1;
+
+    let (value, column_type) = match (value, column_type) {
+        (value, None) => {
+            args.add(value);
+            return Ok(placeholder);
+        }
+        (None, Some(column_type)) => {
+            args.add(None::<&str>);
+            return Ok(format!("CAST({} AS {})", placeholder, column_type));
+        }
+        (Some(value), Some(column_type)) => (value, column_type),
+    };
+
+    let invalid = || {
+        AppError::InvalidQuery(format!(
+            "列 {} 的值无效: {}（期望 {} 类型）",
+            column, value, column_type
+        ))
+    };
+
+    match column_type {
+        "smallint" => args.add(value.trim().parse::<i16>().map_err(|_| invalid())?),
+        "integer" => args.add(value.trim().parse::<i32>().map_err(|_| invalid())?),
+        "bigint" => args.add(value.trim().parse::<i64>().map_err(|_| invalid())?),
+        "real" => args.add(value.trim().parse::<f32>().map_err(|_| invalid())?),
+        "double precision" => args.add(value.trim().parse::<f64>().map_err(|_| invalid())?),
+        "boolean" => args.add(parse_bool(value).ok_or_else(invalid)?),
+        column_type if is_text_type(column_type) => args.add(value),
+        column_type => {
+            if !is_valid_literal(column_type, value) {
+                return Err(invalid());
+            }
+            args.add(value);
+            return Ok(format!("CAST({} AS {})", placeholder, column_type));
+        }
+    }
+
+    Ok(placeholder)
+}
+
+/// 与 text 直接比较不需要转换的类型
+fn is_text_type(column_type: &str) -> bool {
+    matches!(column_type, "text" | "character varying" | "character" | "name")
+}
+
+/// 解析 PostgreSQL 接受的布尔写法
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "t" | "yes" | "y" | "on" | "1" => Some(true),
+        "false" | "f" | "no" | "n" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// 预先校验常见类型的文本写法，避免把格式错误留给数据库报 500
+fn is_valid_literal(column_type: &str, value: &str) -> bool {
+    let value = value.trim();
+    match column_type {
+        "numeric" => value.parse::<f64>().is_ok(),
+        "json" | "jsonb" => serde_json::from_str::<serde_json::Value>(value).is_ok(),
+        array_type if array_type.ends_with("[]") => value.starts_with('{') && value.ends_with('}'),
+        range_type if range_subtype(range_type).is_some() => is_range_literal(value),
+        "uuid" => {
+            let hex: String = value
+                .trim_start_matches('{')
+                .trim_end_matches('}')
+                .chars()
+                .filter(|c| *c != '-')
+                .collect();
+            hex.len() == 32 && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        "date" => is_special_datetime(value) || parse_date(value),
+        "timestamp without time zone" | "timestamp with time zone" => {
+            is_special_datetime(value) || parse_date(value) || parse_timestamp(value)
+        }
+        _ => true,
+    }
+}
+
+/// 内置范围类型的元素类型
+fn range_subtype(column_type: &str) -> Option<&'static str> {
+    let subtype = match column_type {
+        "int4range" => "integer",
+        "int8range" => "bigint",
+        "numrange" => "numeric",
+        "tsrange" => "timestamp without time zone",
+        "tstzrange" => "timestamp with time zone",
+        "daterange" => "date",
+        _ => return None,
+    };
+    Some(subtype)
+}
+
+/// 范围字面量: [a,b) / (a,b] / empty
+fn is_range_literal(value: &str) -> bool {
+    let value = value.trim();
+    value.eq_ignore_ascii_case("empty")
+        || (value.starts_with(['[', '(']) && value.ends_with([']', ')']) && value.contains(','))
+}
+
+/// PostgreSQL 日期 / 时间类型的特殊输入值
+fn is_special_datetime(value: &str) -> bool {
+    matches!(
+        value.to_lowercase().as_str(),
+        "infinity" | "-infinity" | "epoch" | "now" | "today" | "tomorrow" | "yesterday"
+    )
+}
+
+fn parse_date(value: &str) -> bool {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+}
+
+fn parse_timestamp(value: &str) -> bool {
+    if chrono::DateTime::parse_from_rfc3339(value).is_ok() {
+        return true;
+    }
+
+    let value = value.replacen('T', " ", 1);
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d %H:%M"].iter().any(|format| {
+        chrono::NaiveDateTime::parse_from_str(&value, format).is_ok()
+            || chrono::DateTime::parse_from_str(&value, &format!("{}%#z", format)).is_ok()
+    })
+}
+
+/// 将 JSON 值转换为按列类型绑定的文本；json / jsonb 列保留 JSON 序列化形式
+fn json_to_text(value: &serde_json::Value, column_type: &str) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        _ if matches!(column_type, "json" | "jsonb") => Some(value.to_string()),
+        serde_json::Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> QueryParams {
+        let query = pairs
             .iter()
-            .map(|filter| {
-                args.add(&filter.value);
-                let condition = format!("\"{}\" = ${}", filter.column, arg_index);
-                arg_index += 1;
-                condition
-            })
+            .map(|(k, v)| (k.to_string(), v.to_string()))
             .collect();
-        sql.push_str(&conditions.join(" AND "));
+        QueryParams::from_query_map(query).unwrap()
+    }
 
-        sql.push_str(" RETURNING *");
+    fn select_sql(pairs: &[(&str, &str)]) -> String {
+        let builder =
+            SqlBuilder::new("public".to_string(), "orders".to_string(), params(pairs)).unwrap();
+        builder.build_select().unwrap().0
+    }
 
-        Ok((sql, args))
+    #[test]
+    fn test_nested_logic_group() {
+        let sql = select_sql(&[("or", "(status.eq.pending,and(amount.gt.100,region.eq.eu))")]);
+        assert_eq!(
+            sql,
+            "SELECT * FROM \"public\".\"orders\" WHERE (\"status\" = $1 OR (\"amount\" > $2 AND \"region\" = $3))"
+        );
+    }
+
+    #[test]
+    fn test_not_prefix() {
+        let sql = select_sql(&[("status.not.in", "(a,b)")]);
+        assert_eq!(
+            sql,
+            "SELECT * FROM \"public\".\"orders\" WHERE NOT (\"status\" IN ($1, $2))"
+        );
+
+        let sql = select_sql(&[("not.or", "(status.eq.done,region.not.is.null)")]);
+        assert_eq!(
+            sql,
+            "SELECT * FROM \"public\".\"orders\" WHERE NOT ((\"status\" = $1 OR NOT (\"region\" IS NULL)))"
+        );
+    }
+
+    #[test]
+    fn test_quoted_values_in_group() {
+        let p = params(&[("or", "(name.eq.\"a,b\",note.like.*(x)*)")]);
+        match &p.filters[0] {
+            FilterNode::Or(children) => {
+                assert_eq!(children.len(), 2);
+                match &children[0] {
+                    FilterNode::Condition(filter) => assert_eq!(filter.value, "a,b"),
+                    other => panic!("unexpected node: {:?}", other),
+                }
+            }
+            other => panic!("unexpected node: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_logic_group() {
+        let parse = |k: &str, v: &str| {
+            QueryParams::from_query_map([(k.to_string(), v.to_string())].into_iter().collect())
+        };
+        assert!(parse("or", "status.eq.a").is_err());
+        assert!(parse("or", "()").is_err());
+        assert!(parse("or", "(status.unknown.a)").is_err());
+        assert!(parse("and", "(status.eq.a,(x)").is_err());
+        assert!(parse("or", "(bad;col.eq.1)").is_err());
     }
-}
 
+    fn types(columns: &[&str]) -> HashMap<String, String> {
+        columns
+            .iter()
+            .map(|column| (column.to_string(), "integer".to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_embed_select() {
+        let mut p = params(&[
+            ("select", "id,users(username),order_items(id,products(*))"),
+            ("order_items.quantity.gt", "1"),
+            ("order_items.order", "id.desc"),
+            ("order_items.limit", "5"),
+            ("status", "paid"),
+        ]);
+
+        let select = p.select.as_mut().unwrap();
+        assert_eq!(select.len(), 3);
+        for item in select.iter_mut() {
+            if let SelectItem::Embed(embed) = item {
+                if embed.table == "users" {
+                    embed.join = Some(EmbedJoin {
+                        schema: "public".to_string(),
+                        to_many: false,
+                        columns: vec![("id".to_string(), "user_id".to_string())],
+                    });
+                    embed.params.column_types = types(&["id", "username"]);
+                } else {
+                    assert_eq!(embed.params.filters.len(), 1);
+                    assert_eq!(embed.params.limit, Some(5));
+                    embed.join = Some(EmbedJoin {
+                        schema: "public".to_string(),
+                        to_many: true,
+                        columns: vec![("order_id".to_string(), "id".to_string())],
+                    });
+                    embed.params.column_types =
+                        types(&["id", "order_id", "product_id", "quantity"]);
+                    match &mut embed.params.select.as_mut().unwrap()[1] {
+                        SelectItem::Embed(product) => {
+                            product.join = Some(EmbedJoin {
+                                schema: "public".to_string(),
+                                to_many: false,
+                                columns: vec![("id".to_string(), "product_id".to_string())],
+                            });
+                            product.params.column_types = types(&["id"]);
+                        }
+                        other => panic!("unexpected item: {:?}", other),
+                    }
+                }
+            }
+        }
+
+        let builder = SqlBuilder::new("public".to_string(), "orders".to_string(), p).unwrap();
+        let (sql, _) = builder.build_select().unwrap();
+        assert_eq!(
+            sql,
+            "SELECT \"id\", \
+             (SELECT row_to_json(\"_r1\") FROM (SELECT \"_e1\".\"username\" FROM \"public\".\"users\" AS \"_e1\" \
+             WHERE \"_e1\".\"id\" = \"public\".\"orders\".\"user_id\" LIMIT 1) AS \"_r1\") AS \"users\", \
+             (SELECT coalesce(json_agg(\"_r2\"), '[]'::json) FROM (SELECT \"_e2\".\"id\", \
+             (SELECT row_to_json(\"_r3\") FROM (SELECT * FROM \"public\".\"products\" AS \"_e3\" \
+             WHERE \"_e3\".\"id\" = \"_e2\".\"product_id\" LIMIT 1) AS \"_r3\") AS \"products\" \
+             FROM \"public\".\"order_items\" AS \"_e2\" WHERE \"_e2\".\"order_id\" = \"public\".\"orders\".\"id\" \
+             AND \"_e2\".\"quantity\" > $1 ORDER BY \"_e2\".\"id\" DESC LIMIT $2) AS \"_r2\") AS \"order_items\" \
+             FROM \"public\".\"orders\" WHERE \"status\" = $3"
+        );
+    }
+
+    #[test]
+    fn test_unresolved_embed_is_rejected() {
+        let builder = SqlBuilder::new(
+            "public".to_string(),
+            "orders".to_string(),
+            params(&[("select", "id,users(*)")]),
+        )
+        .unwrap();
+        assert!(builder.build_select().is_err());
+    }
+
+    #[test]
+    fn test_embed_unknown_column_is_rejected() {
+        let build = |query: &[(&str, &str)], columns: &[&str]| {
+            let mut p = params(query);
+            if let Some(SelectItem::Embed(embed)) = p.select.as_mut().unwrap().get_mut(1) {
+                embed.join = Some(EmbedJoin {
+                    schema: "public".to_string(),
+                    to_many: true,
+                    columns: vec![("order_id".to_string(), "id".to_string())],
+                });
+                embed.params.column_types = types(columns);
+            }
+            SqlBuilder::new("public".to_string(), "orders".to_string(), p)
+                .unwrap()
+                .build_select()
+        };
+
+        // status 只存在于父表 orders，不能被当作关联引用
+        let query = [("select", "id,order_items(*)"), ("order_items.status", "paid")];
+        assert!(matches!(build(&query, &["id", "order_id"]), Err(AppError::InvalidQuery(_))));
+        // 列信息未知时同样拒绝
+        assert!(build(&query, &[]).is_err());
+
+        let query = [("select", "id,order_items(*)"), ("order_items.quantity", "2")];
+        let (sql, _) = build(&query, &["id", "order_id", "quantity"]).unwrap();
+        assert!(sql.contains("AND \"_e1\".\"quantity\" = $1"));
+    }
+
+    #[test]
+    fn test_keyset_pagination() {
+        let cursor = Cursor::encode(&["2024-01-01 00:00:00+00".to_string(), "42".to_string()]);
+        let mut p = params(&[
+            ("order", "created_at.desc"),
+            ("limit", "10"),
+            ("cursor", &cursor),
+            ("status", "paid"),
+        ]);
+        p.prepare_keyset(&["id".to_string()]).unwrap();
+        p.column_types = HashMap::from([
+            ("created_at".to_string(), "timestamp with time zone".to_string()),
+            ("id".to_string(), "integer".to_string()),
+        ]);
+
+        let builder = SqlBuilder::new("public".to_string(), "orders".to_string(), p).unwrap();
+        let (sql, _) = builder.build_select().unwrap();
+        assert_eq!(
+            sql,
+            "SELECT *, \"created_at\"::text AS \"_cursor_0\", \"id\"::text AS \"_cursor_1\" \
+             FROM \"public\".\"orders\" WHERE \"status\" = $1 AND (\"created_at\", \"id\") < \
+             (CAST($2 AS timestamp with time zone), CAST($3 AS integer)) \
+             ORDER BY \"created_at\" DESC, \"id\" DESC LIMIT $4"
+        );
+    }
+
+    #[test]
+    fn test_keyset_validation() {
+        let mut mixed = params(&[("order", "a.asc,b.desc"), ("cursor", "")]);
+        assert!(mixed.prepare_keyset(&["id".to_string()]).is_err());
+
+        let mut with_offset = params(&[("offset", "10"), ("cursor", "")]);
+        assert!(with_offset.prepare_keyset(&["id".to_string()]).is_err());
+
+        let stale = Cursor::encode(&["1".to_string()]);
+        let mut mismatched = params(&[("order", "created_at"), ("cursor", &stale)]);
+        assert!(mismatched.prepare_keyset(&["id".to_string()]).is_err());
+
+        assert!(Cursor::decode("not-a-cursor").is_err());
+        assert!(Cursor::decode("").unwrap().values.is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_select() {
+        let sql = select_sql(&[
+            ("select", "region,count(),total:sum(amount),avg(amount)"),
+            ("status", "paid"),
+            ("having", "(sum(amount).gt.1000,count().gte.5)"),
+            ("order", "total.desc"),
+        ]);
+        assert_eq!(
+            sql,
+            "SELECT \"region\", COUNT(*) AS \"count\", SUM(\"amount\") AS \"total\", AVG(\"amount\") AS \"avg\" \
+             FROM \"public\".\"orders\" WHERE \"status\" = $1 GROUP BY \"region\" \
+             HAVING (SUM(\"amount\") > CAST($2 AS numeric) AND COUNT(*) >= CAST($3 AS numeric)) \
+             ORDER BY \"total\" DESC"
+        );
+
+        let builder = SqlBuilder::new(
+            "public".to_string(),
+            "orders".to_string(),
+            params(&[("select", "region,count()")]),
+        )
+        .unwrap();
+        assert_eq!(
+            builder.build_count().unwrap().0,
+            "SELECT COUNT(*) FROM (SELECT 1 FROM \"public\".\"orders\" GROUP BY \"region\") AS \"_g\""
+        );
+    }
+
+    #[test]
+    fn test_aggregate_validation() {
+        let parse = |pairs: &[(&str, &str)]| {
+            QueryParams::from_query_map(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+        assert!(parse(&[("select", "count()"), ("having", "(median(amount).gt.1)")]).is_err());
+        assert!(parse(&[("select", "sum()")]).is_err());
+        assert!(parse(&[("select", "sum(amount;drop)")]).is_err());
+        assert!(parse(&[("select", "*,count()")]).is_err());
+        assert!(parse(&[("select", "count(),users(*)")]).is_err());
+        assert!(parse(&[("select", "region"), ("having", "(count().gt.1)")]).is_err());
+        assert!(parse(&[("select", "count()"), ("having", "(region.eq.eu)")]).is_err());
+        assert!(parse(&[("or", "(sum(amount).gt.1,status.eq.a)")]).is_err());
+
+        let mut p = parse(&[("select", "region,count()"), ("cursor", "")]).unwrap();
+        assert!(p.prepare_keyset(&["id".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_typed_binding() {
+        let mut p = params(&[
+            ("id.in", "(1,2)"),
+            ("total", "9.50"),
+            ("paid", "true"),
+            ("created_at.gte", "2024-01-01T00:00:00Z"),
+            ("status.like", "pa*"),
+            ("id.like", "1*"),
+        ]);
+        p.column_types = HashMap::from([
+            ("id".to_string(), "integer".to_string()),
+            ("total".to_string(), "numeric".to_string()),
+            ("paid".to_string(), "boolean".to_string()),
+            ("created_at".to_string(), "timestamp with time zone".to_string()),
+            ("status".to_string(), "character varying".to_string()),
+        ]);
+
+        let builder = SqlBuilder::new("public".to_string(), "orders".to_string(), p).unwrap();
+        let (sql, _) = builder.build_select().unwrap();
+        assert!(sql.contains("\"id\" IN ($"));
+        assert!(sql.contains("\"total\" = CAST($"));
+        assert!(sql.contains(" AS numeric)"));
+        assert!(sql.contains(" AS timestamp with time zone)"));
+        assert!(sql.contains("\"status\" LIKE $"));
+        assert!(sql.contains("\"id\"::text LIKE $"));
+    }
+
+    #[test]
+    fn test_invalid_typed_value() {
+        let mut p = params(&[("id", "abc")]);
+        p.column_types = HashMap::from([("id".to_string(), "bigint".to_string())]);
+        let builder = SqlBuilder::new("public".to_string(), "orders".to_string(), p).unwrap();
+        match builder.build_select() {
+            Err(AppError::InvalidQuery(message)) => assert!(message.contains("id")),
+            other => panic!("unexpected result: {:?}", other.map(|(sql, _)| sql)),
+        }
+
+        assert!(is_valid_literal("uuid", "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11"));
+        assert!(!is_valid_literal("uuid", "a0eebc99"));
+        assert!(is_valid_literal("timestamp with time zone", "2024-01-01 00:00:00+00"));
+        assert!(is_valid_literal("timestamp without time zone", "2024-01-01 10:30:00.123"));
+        assert!(is_valid_literal("date", "today"));
+        assert!(!is_valid_literal("date", "2024-13-01"));
+        assert!(!is_valid_literal("numeric", "12abc"));
+    }
+
+    #[test]
+    fn test_full_text_search() {
+        let mut p = params(&[
+            ("description.wfts(english)", "red shoes"),
+            ("search.fts", "red & shoe"),
+            ("order", "rank(search).desc"),
+        ]);
+        let types = HashMap::from([
+            ("description".to_string(), "text".to_string()),
+            ("search".to_string(), "tsvector".to_string()),
+        ]);
+        p.column_types = types.clone();
+
+        let builder = SqlBuilder::new("public".to_string(), "products".to_string(), p).unwrap();
+        let (sql, _) = builder.build_select().unwrap();
+        assert!(sql.contains(
+            "to_tsvector('english', \"description\") @@ websearch_to_tsquery('english', $"
+        ));
+        assert!(sql.contains("\"search\" @@ to_tsquery($"));
+        assert!(sql.contains("ORDER BY ts_rank(\"search\", to_tsquery($"));
+
+        // 多个全文检索条件时 order=rank 无法确定使用哪一个，没有全文检索条件时同样拒绝
+        let rank = |query: &[(&str, &str)]| {
+            let mut p = params(query);
+            p.column_types = types.clone();
+            SqlBuilder::new("public".to_string(), "products".to_string(), p)
+                .unwrap()
+                .build_select()
+        };
+        assert!(rank(&[("description.fts", "shoe"), ("order", "rank.desc")]).is_ok());
+        assert!(rank(&[
+            ("description.fts", "shoe"),
+            ("search.fts", "red"),
+            ("order", "rank.desc")
+        ])
+        .is_err());
+        assert!(rank(&[("order", "rank.desc")]).is_err());
+        assert!(rank(&[("description.fts", "shoe"), ("order", "rank(search)")]).is_err());
+
+        let parse = |k: &str| {
+            QueryParams::from_query_map([(k.to_string(), "x".to_string())].into_iter().collect())
+        };
+        assert!(parse("description.fts(eng'lish)").is_err());
+        assert!(parse("description.fts(english").is_err());
+    }
+
+    #[test]
+    fn test_json_path() {
+        let mut p = params(&[
+            ("select", "id,attrs->color,sz:attrs->>size"),
+            ("attrs->>size.eq", "XL"),
+            ("attrs.cs", "{\"tag\":\"new\"}"),
+            ("attrs->dims.haskey", "w"),
+        ]);
+        p.column_types = HashMap::from([("attrs".to_string(), "jsonb".to_string())]);
+
+        let builder = SqlBuilder::new("public".to_string(), "items".to_string(), p).unwrap();
+        let (sql, _) = builder.build_select().unwrap();
+        assert!(sql.starts_with(
+            "SELECT \"id\", (\"attrs\"->$1) AS \"color\", (\"attrs\"->>$2) AS \"sz\" FROM"
+        ));
+        assert!(sql.contains("\"attrs\" @> CAST($"));
+        assert!(sql.contains("(\"attrs\"->>$"));
+        assert!(sql.contains(") ? $"));
+
+        let parse = |k: &str, v: &str| {
+            QueryParams::from_query_map([(k.to_string(), v.to_string())].into_iter().collect())
+        };
+        assert!(parse("select", "attrs->>a->b").is_err());
+        assert!(parse("select", "attrs->'x'").is_err());
+        assert!(parse("select", "attrs->").is_err());
+        assert!(parse("attrs->a;b.eq", "1").is_err());
+
+        let mut p = params(&[("name->>x.eq", "1")]);
+        p.column_types = HashMap::from([("name".to_string(), "text".to_string())]);
+        let builder = SqlBuilder::new("public".to_string(), "items".to_string(), p).unwrap();
+        assert!(builder.build_select().is_err());
+    }
+
+    #[test]
+    fn test_array_and_range_operators() {
+        let mut p = params(&[
+            ("tags.ov", "{a,b}"),
+            ("booking.cs", "2024-01-15"),
+            ("or", "(booking.adj.\"[2024-02-01,2024-03-01)\",tags.cd.{a,b,c})"),
+        ]);
+        p.column_types = HashMap::from([
+            ("tags".to_string(), "text[]".to_string()),
+            ("booking".to_string(), "tstzrange".to_string()),
+        ]);
+
+        let builder = SqlBuilder::new("public".to_string(), "bookings".to_string(), p).unwrap();
+        let (sql, _) = builder.build_select().unwrap();
+        assert!(sql.contains("\"tags\" && CAST($"));
+        assert!(sql.contains("\"booking\" @> CAST($"));
+        assert!(sql.contains(" AS timestamp with time zone)"));
+        assert!(sql.contains("\"booking\" -|- CAST($"));
+        assert!(sql.contains(" AS tstzrange)"));
+        assert!(sql.contains("\"tags\" <@ CAST($"));
+
+        let mut p = params(&[("booking.sl", "2024-01-01")]);
+        p.column_types = HashMap::from([("booking".to_string(), "tstzrange".to_string())]);
+        let builder = SqlBuilder::new("public".to_string(), "bookings".to_string(), p).unwrap();
+        assert!(builder.build_select().is_err());
+    }
+
+    #[test]
+    fn test_mutation_filters() {
+        let query = |pairs: &[(&str, &str)]| {
+            QueryParams::from_mutation_query(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+
+        let mut p = query(&[
+            ("created_at.lt", "2024-01-01"),
+            ("status.in", "a,b"),
+            ("or", "(note.is.null,id.gt.10)"),
+        ])
+        .unwrap();
+        p.column_types = HashMap::from([
+            ("created_at".to_string(), "date".to_string()),
+            ("status".to_string(), "text".to_string()),
+            ("id".to_string(), "integer".to_string()),
+        ]);
+        let builder = SqlBuilder::new("public".to_string(), "orders".to_string(), p).unwrap();
+
+        let (sql, _) = builder.build_update(&serde_json::json!({"status": "done"})).unwrap();
+        assert!(sql.starts_with("UPDATE \"public\".\"orders\" SET \"status\" = $1 WHERE "));
+        assert!(sql.contains("\"created_at\" < CAST($"));
+        assert!(sql.contains("\"status\" IN ($"));
+        assert!(sql.contains("(\"note\" IS NULL OR \"id\" > $"));
+
+        let (sql, _) = builder.build_delete().unwrap();
+        assert!(sql.starts_with("DELETE FROM \"public\".\"orders\" WHERE "));
+        assert!(sql.contains("\"status\" IN ($"));
+
+        // 无法识别的操作符和只适用于查询的参数直接报错
+        assert!(query(&[("id.between", "1")]).is_err());
+        assert!(query(&[("id.eq.x.y", "1")]).is_err());
+        assert!(query(&[("id", "1"), ("limit", "1")]).is_err());
+        assert!(query(&[("or", "(id.between.1)")]).is_err());
+    }
+
+    #[test]
+    fn test_bulk_insert() {
+        let builder = |pairs: &[(&str, &str)]| {
+            SqlBuilder::new("public".to_string(), "users".to_string(), params(pairs)).unwrap()
+        };
+
+        let rows = serde_json::json!([
+            {"name": "a", "age": 1},
+            {"name": "b"},
+        ]);
+
+        // 键不一致时必须指定 columns，缺失的列取默认值
+        assert!(builder(&[]).build_insert(&rows).is_err());
+        let statements = builder(&[("columns", "name,age")]).build_insert(&rows).unwrap();
+        assert_eq!(statements.len(), 1);
+        assert_eq!(
+            statements[0].0,
+            "INSERT INTO \"public\".\"users\" (\"name\", \"age\") VALUES ($1, $2), ($3, DEFAULT) RETURNING *"
+        );
+
+        // 单个对象与数组使用同一套逻辑
+        let statements = builder(&[]).build_insert(&serde_json::json!({"name": "a"})).unwrap();
+        assert_eq!(
+            statements[0].0,
+            "INSERT INTO \"public\".\"users\" (\"name\") VALUES ($1) RETURNING *"
+        );
+
+        // 大批量数据按行数和参数上限分块
+        let rows = serde_json::Value::Array(vec![serde_json::json!({"name": "a"}); 2500]);
+        assert_eq!(builder(&[]).build_insert(&rows).unwrap().len(), 3);
+
+        // 255 列 × 257 行正好占满参数上限，RETURNING 中的 JSON 路径参数需要另起一条语句
+        let row: serde_json::Map<String, serde_json::Value> =
+            (0..255).map(|i| (format!("c{}", i), serde_json::json!(i))).collect();
+        let rows = serde_json::Value::Array(vec![serde_json::Value::Object(row); 257]);
+        assert_eq!(builder(&[]).build_insert(&rows).unwrap().len(), 1);
+        assert_eq!(
+            builder(&[("select", "c0,attrs->>size")]).build_insert(&rows).unwrap().len(),
+            2
+        );
+
+        assert!(builder(&[]).build_insert(&serde_json::json!([])).is_err());
+        assert!(builder(&[]).build_insert(&serde_json::json!([1, 2])).is_err());
+        assert!(QueryParams::from_query_map(HashMap::from([(
+            "columns".to_string(),
+            "name;drop".to_string()
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_upsert() {
+        let builder = |pairs: &[(&str, &str)]| {
+            SqlBuilder::new("public".to_string(), "stock".to_string(), params(pairs)).unwrap()
+        };
+        let row = serde_json::json!({"sku": "a", "warehouse": "w1", "qty": 5});
+
+        let (sql, _) = &builder(&[("on_conflict", "sku,warehouse")])
+            .build_upsert(&row, Resolution::MergeDuplicates)
+            .unwrap()[0];
+        assert!(sql.ends_with(
+            "ON CONFLICT (\"sku\", \"warehouse\") DO UPDATE SET \"qty\" = EXCLUDED.\"qty\" RETURNING *"
+        ));
+
+        let (sql, _) = &builder(&[("on_conflict", "sku")])
+            .build_upsert(&row, Resolution::IgnoreDuplicates)
+            .unwrap()[0];
+        assert!(sql.ends_with("ON CONFLICT (\"sku\") DO NOTHING RETURNING *"));
+
+        // 只有冲突列时写回冲突列本身
+        let (sql, _) = &builder(&[("on_conflict", "sku")])
+            .build_upsert(&serde_json::json!({"sku": "a"}), Resolution::MergeDuplicates)
+            .unwrap()[0];
+        assert!(sql.contains("DO UPDATE SET \"sku\" = EXCLUDED.\"sku\""));
+
+        assert!(builder(&[])
+            .build_upsert(&row, Resolution::MergeDuplicates)
+            .is_err());
+
+        // 合并写入时同一批数据中冲突键重复直接拒绝，NULL 冲突键和 DO NOTHING 不受影响
+        let rows = serde_json::json!([
+            {"sku": "a", "warehouse": "w1", "qty": 1},
+            {"sku": "b", "warehouse": null, "qty": 2},
+            {"sku": "b", "warehouse": null, "qty": 3},
+            {"sku": "a", "warehouse": "w1", "qty": 4}
+        ]);
+        let conflict = builder(&[("on_conflict", "sku,warehouse")]);
+        match conflict.build_upsert(&rows, Resolution::MergeDuplicates) {
+            Err(AppError::InvalidQuery(msg)) => assert!(msg.starts_with("第 4 条数据与第 1 条数据")),
+            other => panic!("unexpected result: {:?}", other.map(|s| s.len())),
+        }
+        assert!(conflict.build_upsert(&rows, Resolution::IgnoreDuplicates).is_ok());
+
+        let (sql, _) = &builder(&[("on_conflict", "sku")])
+            .overriding_system_value()
+            .build_upsert(&serde_json::json!({"sku": "a", "qty": 1}), Resolution::MergeDuplicates)
+            .unwrap()[0];
+        assert!(sql.contains(") OVERRIDING SYSTEM VALUE VALUES ($1, $2)"));
+    }
+
+    #[test]
+    fn test_filter_by_key() {
+        let key = vec!["order_id".to_string(), "line".to_string()];
+        let mut p = params(&[]);
+        p.filter_by_key(&key, "42,3").unwrap();
+        p.column_types = HashMap::from([
+            ("order_id".to_string(), "integer".to_string()),
+            ("line".to_string(), "smallint".to_string()),
+        ]);
+        let builder = SqlBuilder::new("public".to_string(), "order_items".to_string(), p).unwrap();
+        let (sql, _) = builder.build_delete().unwrap();
+        assert_eq!(
+            sql,
+            "DELETE FROM \"public\".\"order_items\" WHERE \"order_id\" = $1 AND \"line\" = $2 RETURNING *"
+        );
+
+        assert!(params(&[]).filter_by_key(&key, "42").is_err());
+        assert!(params(&[]).filter_by_key(&key, "42,").is_err());
+        assert!(params(&[]).filter_by_key(&key, "1,2,3").is_err());
+    }
+
+    #[test]
+    fn test_returning() {
+        let builder = |pairs: &[(&str, &str)]| {
+            SqlBuilder::new("public".to_string(), "orders".to_string(), params(pairs)).unwrap()
+        };
+        let data = serde_json::json!({"status": "paid"});
+
+        let (sql, _) = builder(&[("id", "1"), ("select", "id,updated_at")])
+            .build_update(&data)
+            .unwrap();
+        assert!(sql.ends_with(" RETURNING \"id\", \"updated_at\""));
+
+        let (sql, _) = &builder(&[("select", "id,size:attrs->>size")])
+            .build_insert(&data)
+            .unwrap()[0];
+        assert!(sql.ends_with(" RETURNING \"id\", (\"attrs\"->>$2) AS \"size\""));
+
+        let (sql, _) = builder(&[("id", "1")]).without_returning().build_delete().unwrap();
+        assert_eq!(sql, "DELETE FROM \"public\".\"orders\" WHERE \"id\" = $1");
+
+        assert!(builder(&[("id", "1"), ("select", "count()")])
+            .build_delete()
+            .is_err());
+    }
+
+    #[test]
+    fn test_select_json() {
+        let builder = |pairs: &[(&str, &str)]| {
+            SqlBuilder::new("public".to_string(), "orders".to_string(), params(pairs)).unwrap()
+        };
+
+        let (sql, _) = builder(&[]).build_select_json_rows().unwrap();
+        assert_eq!(
+            sql,
+            "SELECT row_to_json(\"_t\")::text FROM (SELECT * FROM \"public\".\"orders\") AS \"_t\""
+        );
+
+        let (sql, _) = builder(&[("limit", "10")]).build_select_json_page().unwrap();
+        assert_eq!(
+            sql,
+            "SELECT row_to_json(\"_t\")::text, COUNT(*) OVER () FROM (SELECT * FROM \"public\".\"orders\" LIMIT $1) AS \"_t\""
+        );
+    }
+}
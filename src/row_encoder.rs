@@ -0,0 +1,924 @@
+//! 数据库行到 JSON 的统一编码
+//!
+//! 按列类型的 OID 直接解析 PostgreSQL 二进制格式，覆盖全部内置类型及其数组、
+//! 枚举、域、复合类型和范围类型。为避免精度或语义丢失：
+//! - numeric / money 输出为字符串
+//! - bytea 输出为 base64
+//! - timestamptz 输出为 RFC 3339（UTC），timestamp / date / time 输出为 ISO 8601
+//! - interval 输出为 ISO 8601 时长（如 `P1Y2M3DT4H5M6S`）
+//! - 范围类型输出为 PostgreSQL 文本形式（如 `[1,10)`）
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Utc};
+use serde_json::{Map, Number, Value};
+use sqlx::postgres::{PgRow, PgTypeInfo, PgTypeKind, PgValueFormat, PgValueRef};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// 将一行转换为 JSON 对象（键为列名）
+pub fn row_to_json(row: &PgRow) -> Value {
+    let mut obj = Map::new();
+    for column in row.columns() {
+        obj.insert(column.name().to_string(), column_to_json(row, column.ordinal()));
+    }
+    Value::Object(obj)
+}
+
+/// 将多行转换为 JSON 数组
+pub fn rows_to_json(rows: &[PgRow]) -> Value {
+    Value::Array(rows.iter().map(row_to_json).collect())
+}
+
+/// 将单列的值转换为 JSON（NULL 或无法解析时为 null）
+pub fn column_to_json(row: &PgRow, idx: usize) -> Value {
+    match row.try_get_raw(idx) {
+        Ok(value) if !value.is_null() => encode_value(&value),
+        _ => Value::Null,
+    }
+}
+
+/// 将单列的值转换为文本（用于 CSV 等纯文本导出，NULL 为 "NULL"）
+pub fn column_to_string(row: &PgRow, idx: usize) -> String {
+    match column_to_json(row, idx) {
+        Value::Null => "NULL".to_string(),
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+fn encode_value(value: &PgValueRef<'_>) -> Value {
+    let type_info = value.type_info().into_owned();
+    let bytes = match value.as_bytes() {
+        Ok(bytes) => bytes,
+        Err(_) => return Value::Null,
+    };
+
+    match value.format() {
+        PgValueFormat::Binary => decode(bytes, &type_info).unwrap_or_else(|| {
+            tracing::debug!("无法解析类型 {} 的值，返回 null", type_info.name());
+            Value::Null
+        }),
+        PgValueFormat::Text => decode_text(bytes, type_info.oid().map(|oid| oid.0)),
+    }
+}
+
+/// 文本格式的值（简单查询协议）：数字、布尔和 JSON 按类型解析，其余原样返回
+fn decode_text(bytes: &[u8], oid: Option<u32>) -> Value {
+    let text = String::from_utf8_lossy(bytes);
+    match oid {
+        Some(oids::BOOL) => Value::Bool(text == "t"),
+        Some(oids::INT2 | oids::INT4 | oids::INT8 | oids::OID) => text
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::String(text.into_owned())),
+        Some(oids::FLOAT4 | oids::FLOAT8) => text
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .unwrap_or_else(|| Value::String(text.into_owned())),
+        Some(oids::JSON | oids::JSONB) => {
+            serde_json::from_str(&text).unwrap_or_else(|_| Value::String(text.into_owned()))
+        }
+        _ => Value::String(text.into_owned()),
+    }
+}
+
+/// 二进制格式的值：先按类型种类（数组、范围、枚举等）分派，再按 OID 解析内置类型
+fn decode(buf: &[u8], ty: &PgTypeInfo) -> Option<Value> {
+    match ty.kind() {
+        PgTypeKind::Array(elem) => decode_array(buf, &|bytes| decode(bytes, elem)),
+        PgTypeKind::Range(subtype) => {
+            decode_range(buf, &|bytes| decode(bytes, subtype)).map(Value::String)
+        }
+        PgTypeKind::Domain(base) => decode(buf, base),
+        PgTypeKind::Enum(_) => utf8(buf),
+        PgTypeKind::Composite(fields) => decode_record(buf, Some(fields)),
+        PgTypeKind::Simple | PgTypeKind::Pseudo => {
+            let oid = ty.oid()?.0;
+            // 扩展类型（如 citext）的二进制格式通常就是文本
+            decode_builtin(buf, oid).or_else(|| utf8(buf))
+        }
+    }
+}
+
+/// 内置类型的 OID
+mod oids {
+    pub const BOOL: u32 = 16;
+    pub const BYTEA: u32 = 17;
+    pub const CHAR: u32 = 18;
+    pub const NAME: u32 = 19;
+    pub const INT8: u32 = 20;
+    pub const INT2: u32 = 21;
+    pub const INT2VECTOR: u32 = 22;
+    pub const INT4: u32 = 23;
+    pub const REGPROC: u32 = 24;
+    pub const TEXT: u32 = 25;
+    pub const OID: u32 = 26;
+    pub const TID: u32 = 27;
+    pub const XID: u32 = 28;
+    pub const CID: u32 = 29;
+    pub const OIDVECTOR: u32 = 30;
+    pub const JSON: u32 = 114;
+    pub const XML: u32 = 142;
+    pub const POINT: u32 = 600;
+    pub const LSEG: u32 = 601;
+    pub const PATH: u32 = 602;
+    pub const BOX: u32 = 603;
+    pub const POLYGON: u32 = 604;
+    pub const LINE: u32 = 628;
+    pub const CIDR: u32 = 650;
+    pub const FLOAT4: u32 = 700;
+    pub const FLOAT8: u32 = 701;
+    pub const UNKNOWN: u32 = 705;
+    pub const CIRCLE: u32 = 718;
+    pub const MACADDR8: u32 = 774;
+    pub const MONEY: u32 = 790;
+    pub const MACADDR: u32 = 829;
+    pub const INET: u32 = 869;
+    pub const BPCHAR: u32 = 1042;
+    pub const VARCHAR: u32 = 1043;
+    pub const DATE: u32 = 1082;
+    pub const TIME: u32 = 1083;
+    pub const TIMESTAMP: u32 = 1114;
+    pub const TIMESTAMPTZ: u32 = 1184;
+    pub const INTERVAL: u32 = 1186;
+    pub const TIMETZ: u32 = 1266;
+    pub const BIT: u32 = 1560;
+    pub const VARBIT: u32 = 1562;
+    pub const NUMERIC: u32 = 1700;
+    pub const REFCURSOR: u32 = 1790;
+    pub const REGPROCEDURE: u32 = 2202;
+    pub const REGOPER: u32 = 2203;
+    pub const REGOPERATOR: u32 = 2204;
+    pub const REGCLASS: u32 = 2205;
+    pub const REGTYPE: u32 = 2206;
+    pub const RECORD: u32 = 2249;
+    pub const VOID: u32 = 2278;
+    pub const UUID: u32 = 2950;
+    pub const TXID_SNAPSHOT: u32 = 2970;
+    pub const PG_LSN: u32 = 3220;
+    pub const TSVECTOR: u32 = 3614;
+    pub const TSQUERY: u32 = 3615;
+    pub const REGCONFIG: u32 = 3734;
+    pub const REGDICTIONARY: u32 = 3769;
+    pub const JSONB: u32 = 3802;
+    pub const INT4RANGE: u32 = 3904;
+    pub const NUMRANGE: u32 = 3906;
+    pub const TSRANGE: u32 = 3908;
+    pub const TSTZRANGE: u32 = 3910;
+    pub const DATERANGE: u32 = 3912;
+    pub const INT8RANGE: u32 = 3926;
+    pub const JSONPATH: u32 = 4072;
+    pub const REGNAMESPACE: u32 = 4089;
+    pub const REGROLE: u32 = 4096;
+    pub const REGCOLLATION: u32 = 4191;
+    pub const INT4MULTIRANGE: u32 = 4451;
+    pub const NUMMULTIRANGE: u32 = 4532;
+    pub const TSMULTIRANGE: u32 = 4533;
+    pub const TSTZMULTIRANGE: u32 = 4534;
+    pub const DATEMULTIRANGE: u32 = 4535;
+    pub const INT8MULTIRANGE: u32 = 4536;
+    pub const PG_SNAPSHOT: u32 = 5038;
+    pub const XID8: u32 = 5069;
+}
+
+/// 按 OID 解析内置标量类型；未知类型或数据格式错误时返回 None
+fn decode_builtin(buf: &[u8], oid: u32) -> Option<Value> {
+    use oids::*;
+
+    let mut r = Reader(buf);
+    let value = match oid {
+        BOOL => Value::Bool(r.u8()? != 0),
+        BYTEA => Value::String(STANDARD.encode(buf)),
+        CHAR | NAME | TEXT | BPCHAR | VARCHAR | XML | UNKNOWN | REFCURSOR => {
+            Value::String(String::from_utf8_lossy(buf).into_owned())
+        }
+        INT2 => Value::from(r.i16()?),
+        INT4 => Value::from(r.i32()?),
+        INT8 => Value::from(r.i64()?),
+        OID | REGPROC | XID | CID | REGPROCEDURE | REGOPER | REGOPERATOR | REGCLASS
+        | REGTYPE | REGCONFIG | REGDICTIONARY | REGNAMESPACE | REGROLE | REGCOLLATION => {
+            Value::from(r.u32()?)
+        }
+        XID8 => Value::from(r.u64()?),
+        FLOAT4 => float(r.f32()?.to_string().parse().ok()?),
+        FLOAT8 => float(r.f64()?),
+        NUMERIC => Value::String(numeric(buf)?),
+        MONEY => Value::String(money(r.i64()?)),
+        JSON => serde_json::from_slice(buf).ok()?,
+        JSONB => serde_json::from_slice(buf.get(1..)?).ok()?,
+        JSONPATH => Value::String(String::from_utf8_lossy(buf.get(1..)?).into_owned()),
+        UUID => Value::String(uuid(buf)?),
+        DATE => Value::String(date(r.i32()?)?),
+        TIME => Value::String(time(r.i64()?)?),
+        TIMETZ => {
+            let time = time(r.i64()?)?;
+            // 二进制格式中的偏移量以秒计，向西为正
+            Value::String(format!("{}{}", time, utc_offset(-r.i32()?)))
+        }
+        TIMESTAMP => Value::String(timestamp(r.i64()?, false)?),
+        TIMESTAMPTZ => Value::String(timestamp(r.i64()?, true)?),
+        INTERVAL => {
+            let micros = r.i64()?;
+            let days = r.i32()?;
+            let months = r.i32()?;
+            Value::String(interval(months, days, micros))
+        }
+        INET | CIDR => Value::String(inet(&mut r)?),
+        MACADDR | MACADDR8 => Value::String(
+            buf.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+        ),
+        BIT | VARBIT => {
+            let len = usize::try_from(r.i32()?).ok()?;
+            let bytes = r.rest();
+            let bits = (0..len)
+                .map(|i| bytes.get(i / 8).map(|b| if b >> (7 - i % 8) & 1 == 1 { '1' } else { '0' }))
+                .collect::<Option<String>>()?;
+            Value::String(bits)
+        }
+        POINT => Value::String(point(&mut r)?),
+        LSEG => Value::String(format!("[{},{}]", point(&mut r)?, point(&mut r)?)),
+        BOX => Value::String(format!("{},{}", point(&mut r)?, point(&mut r)?)),
+        LINE => Value::String(format!("{{{},{},{}}}", r.f64()?, r.f64()?, r.f64()?)),
+        CIRCLE => Value::String(format!("<{},{}>", point(&mut r)?, r.f64()?)),
+        PATH => {
+            let closed = r.u8()? != 0;
+            let points = points(&mut r)?;
+            Value::String(if closed {
+                format!("({})", points)
+            } else {
+                format!("[{}]", points)
+            })
+        }
+        POLYGON => Value::String(format!("({})", points(&mut r)?)),
+        TID => Value::String(format!("({},{})", r.u32()?, r.u16()?)),
+        PG_LSN => {
+            let lsn = r.u64()?;
+            Value::String(format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF))
+        }
+        TXID_SNAPSHOT | PG_SNAPSHOT => {
+            let count = r.i32()?;
+            let xmin = r.u64()?;
+            let xmax = r.u64()?;
+            let xips = (0..count)
+                .map(|_| r.u64().map(|xip| xip.to_string()))
+                .collect::<Option<Vec<_>>>()?;
+            Value::String(format!("{}:{}:{}", xmin, xmax, xips.join(",")))
+        }
+        TSVECTOR => Value::String(tsvector(&mut r)?),
+        TSQUERY => Value::String(tsquery(&mut r)?),
+        INT2VECTOR => decode_array(buf, &|bytes| decode_builtin(bytes, INT2))?,
+        OIDVECTOR => decode_array(buf, &|bytes| decode_builtin(bytes, OID))?,
+        RECORD => decode_record(buf, None)?,
+        VOID => Value::Null,
+        INT4RANGE | NUMRANGE | TSRANGE | TSTZRANGE | DATERANGE | INT8RANGE => {
+            let subtype = range_subtype(oid)?;
+            Value::String(decode_range(buf, &|bytes| decode_builtin(bytes, subtype))?)
+        }
+        INT4MULTIRANGE | NUMMULTIRANGE | TSMULTIRANGE | TSTZMULTIRANGE | DATEMULTIRANGE
+        | INT8MULTIRANGE => {
+            let subtype = range_subtype(oid)?;
+            let count = r.i32()?;
+            let ranges = (0..count)
+                .map(|_| {
+                    let len = usize::try_from(r.i32()?).ok()?;
+                    decode_range(r.take(len)?, &|bytes| decode_builtin(bytes, subtype))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Value::String(format!("{{{}}}", ranges.join(",")))
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// 内置范围 / 多范围类型的元素类型
+fn range_subtype(oid: u32) -> Option<u32> {
+    use oids::*;
+
+    Some(match oid {
+        INT4RANGE | INT4MULTIRANGE => INT4,
+        NUMRANGE | NUMMULTIRANGE => NUMERIC,
+        TSRANGE | TSMULTIRANGE => TIMESTAMP,
+        TSTZRANGE | TSTZMULTIRANGE => TIMESTAMPTZ,
+        DATERANGE | DATEMULTIRANGE => DATE,
+        INT8RANGE | INT8MULTIRANGE => INT8,
+        _ => return None,
+    })
+}
+
+/// 按大端序顺序读取二进制数据
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.array::<1>().map(|b| b[0])
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.array().map(i16::from_be_bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.array().map(i32::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        self.array().map(i64::from_be_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.array().map(u64::from_be_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.array().map(f32::from_be_bytes)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.array().map(f64::from_be_bytes)
+    }
+
+    /// 以 \0 结尾的字符串
+    fn cstr(&mut self) -> Option<String> {
+        let end = self.0.iter().position(|b| *b == 0)?;
+        let s = String::from_utf8_lossy(&self.0[..end]).into_owned();
+        self.0 = &self.0[end + 1..];
+        Some(s)
+    }
+
+    /// 带 4 字节长度前缀的值，长度为 -1 表示 NULL
+    fn value(&mut self) -> Option<Option<&'a [u8]>> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Some(None);
+        }
+        self.take(len as usize).map(Some)
+    }
+}
+
+fn utf8(buf: &[u8]) -> Option<Value> {
+    std::str::from_utf8(buf).ok().map(|s| Value::String(s.to_string()))
+}
+
+/// NaN / Infinity 无法用 JSON 数字表示，输出为字符串
+fn float(v: f64) -> Value {
+    match Number::from_f64(v) {
+        Some(n) => Value::Number(n),
+        None if v.is_nan() => Value::String("NaN".to_string()),
+        None if v > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+/// numeric：以万进制存储的数字组，按 dscale 输出完整精度
+fn numeric(buf: &[u8]) -> Option<String> {
+    const NEG: u16 = 0x4000;
+    const NAN: u16 = 0xC000;
+    const PINF: u16 = 0xD000;
+    const NINF: u16 = 0xF000;
+
+    let mut r = Reader(buf);
+    let ndigits = r.i16()?;
+    let weight = r.i16()? as i32;
+    let sign = r.u16()?;
+    let dscale = r.u16()? as usize;
+    let digits = (0..ndigits).map(|_| r.i16()).collect::<Option<Vec<_>>>()?;
+
+    match sign {
+        NAN => return Some("NaN".to_string()),
+        PINF => return Some("Infinity".to_string()),
+        NINF => return Some("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let digit = |idx: i32| -> i16 {
+        usize::try_from(idx)
+            .ok()
+            .and_then(|idx| digits.get(idx).copied())
+            .unwrap_or(0)
+    };
+
+    let mut out = String::new();
+    if sign == NEG {
+        out.push('-');
+    }
+    if weight < 0 {
+        out.push('0');
+    } else {
+        for idx in 0..=weight {
+            if idx == 0 {
+                write!(out, "{}", digit(idx)).ok()?;
+            } else {
+                write!(out, "{:04}", digit(idx)).ok()?;
+            }
+        }
+    }
+
+    if dscale > 0 {
+        let mut frac = String::new();
+        let mut idx = weight + 1;
+        while frac.len() < dscale {
+            write!(frac, "{:04}", digit(idx)).ok()?;
+            idx += 1;
+        }
+        frac.truncate(dscale);
+        out.push('.');
+        out.push_str(&frac);
+    }
+    Some(out)
+}
+
+/// money：以分为单位的整数（按两位小数输出）
+fn money(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let abs = cents.unsigned_abs();
+    format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+}
+
+fn uuid(buf: &[u8]) -> Option<String> {
+    if buf.len() != 16 {
+        return None;
+    }
+    let hex = hex::encode(buf);
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+/// PostgreSQL 日期 / 时间戳的纪元
+fn pg_epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2000, 1, 1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .expect("有效的纪元日期")
+}
+
+fn date(days: i32) -> Option<String> {
+    match days {
+        i32::MAX => Some("infinity".to_string()),
+        i32::MIN => Some("-infinity".to_string()),
+        _ => pg_epoch()
+            .date()
+            .checked_add_signed(Duration::days(days.into()))
+            .map(|d| d.format("%Y-%m-%d").to_string()),
+    }
+}
+
+fn time(micros: i64) -> Option<String> {
+    // time 允许 24:00:00
+    if micros == 86_400_000_000 {
+        return Some("24:00:00".to_string());
+    }
+    let secs = u32::try_from(micros.div_euclid(1_000_000)).ok()?;
+    let nanos = u32::try_from(micros.rem_euclid(1_000_000) * 1000).ok()?;
+    NaiveTime::from_num_seconds_from_midnight_opt(secs, nanos)
+        .map(|t| t.format("%H:%M:%S%.f").to_string())
+}
+
+fn timestamp(micros: i64, with_tz: bool) -> Option<String> {
+    match micros {
+        i64::MAX => return Some("infinity".to_string()),
+        i64::MIN => return Some("-infinity".to_string()),
+        _ => {}
+    }
+    let naive = pg_epoch().checked_add_signed(Duration::microseconds(micros))?;
+    Some(if with_tz {
+        DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    } else {
+        naive.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+    })
+}
+
+/// 东偏为正的秒数，输出为 +08:00 形式
+fn utc_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let abs = seconds.unsigned_abs();
+    let mut out = format!("{}{:02}:{:02}", sign, abs / 3600, abs % 3600 / 60);
+    let secs = abs % 60;
+    if secs > 0 {
+        write!(out, ":{:02}", secs).ok();
+    }
+    out
+}
+
+/// interval：输出 ISO 8601 时长，各分量分别带符号（与 IntervalStyle = iso_8601 一致）
+fn interval(months: i32, days: i32, micros: i64) -> String {
+    if months == 0 && days == 0 && micros == 0 {
+        return "PT0S".to_string();
+    }
+
+    let mut out = String::from("P");
+    let (years, months) = (months / 12, months % 12);
+    for (value, unit) in [(years, 'Y'), (months, 'M'), (days, 'D')] {
+        if value != 0 {
+            write!(out, "{}{}", value, unit).ok();
+        }
+    }
+
+    if micros != 0 {
+        out.push('T');
+        let hours = micros / 3_600_000_000;
+        let minutes = micros % 3_600_000_000 / 60_000_000;
+        let micros = micros % 60_000_000;
+        if hours != 0 {
+            write!(out, "{}H", hours).ok();
+        }
+        if minutes != 0 {
+            write!(out, "{}M", minutes).ok();
+        }
+        if micros != 0 {
+            let sign = if micros < 0 { "-" } else { "" };
+            let abs = micros.unsigned_abs();
+            write!(out, "{}{}", sign, abs / 1_000_000).ok();
+            let frac = abs % 1_000_000;
+            if frac != 0 {
+                let frac = format!("{:06}", frac);
+                write!(out, ".{}", frac.trim_end_matches('0')).ok();
+            }
+            out.push('S');
+        }
+    }
+    out
+}
+
+fn inet(r: &mut Reader<'_>) -> Option<String> {
+    const PGSQL_AF_INET: u8 = 2;
+    const PGSQL_AF_INET6: u8 = 3;
+
+    let family = r.u8()?;
+    let bits = r.u8()?;
+    let is_cidr = r.u8()? != 0;
+    let len = r.u8()? as usize;
+    let addr = r.take(len)?;
+
+    let (addr, max_bits) = match family {
+        PGSQL_AF_INET => (Ipv4Addr::from(<[u8; 4]>::try_from(addr).ok()?).to_string(), 32),
+        PGSQL_AF_INET6 => (Ipv6Addr::from(<[u8; 16]>::try_from(addr).ok()?).to_string(), 128),
+        _ => return None,
+    };
+    Some(if is_cidr || bits != max_bits {
+        format!("{}/{}", addr, bits)
+    } else {
+        addr
+    })
+}
+
+fn point(r: &mut Reader<'_>) -> Option<String> {
+    Some(format!("({},{})", r.f64()?, r.f64()?))
+}
+
+fn points(r: &mut Reader<'_>) -> Option<String> {
+    let count = r.i32()?;
+    let points = (0..count).map(|_| point(r)).collect::<Option<Vec<_>>>()?;
+    Some(points.join(","))
+}
+
+/// tsvector：'词素':位置权重 ...
+fn tsvector(r: &mut Reader<'_>) -> Option<String> {
+    let count = r.i32()?;
+    let mut lexemes = Vec::new();
+    for _ in 0..count {
+        let mut lexeme = quote_lexeme(&r.cstr()?);
+        let npos = r.u16()?;
+        let positions = (0..npos)
+            .map(|_| {
+                let pos = r.u16()?;
+                let weight = match pos >> 14 {
+                    3 => "A",
+                    2 => "B",
+                    1 => "C",
+                    _ => "",
+                };
+                Some(format!("{}{}", pos & 0x3FFF, weight))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if !positions.is_empty() {
+            write!(lexeme, ":{}", positions.join(",")).ok()?;
+        }
+        lexemes.push(lexeme);
+    }
+    Some(lexemes.join(" "))
+}
+
+/// tsquery：二进制中按前缀顺序存储（运算符、右操作数、左操作数）
+fn tsquery(r: &mut Reader<'_>) -> Option<String> {
+    enum Item {
+        Operand(String),
+        Not,
+        Binary(String),
+    }
+
+    fn render(items: &[Item], idx: usize) -> Option<(String, usize)> {
+        match items.get(idx)? {
+            Item::Operand(text) => Some((text.clone(), idx + 1)),
+            Item::Not => {
+                let (operand, next) = render(items, idx + 1)?;
+                Some((format!("!{}", operand), next))
+            }
+            Item::Binary(op) => {
+                let (right, next) = render(items, idx + 1)?;
+                let (left, next) = render(items, next)?;
+                Some((format!("( {} {} {} )", left, op, right), next))
+            }
+        }
+    }
+
+    let count = r.i32()?;
+    let mut items = Vec::new();
+    for _ in 0..count {
+        let item = match r.u8()? {
+            1 => {
+                let weight = r.u8()?;
+                let prefix = r.u8()? != 0;
+                let mut text = quote_lexeme(&r.cstr()?);
+                let mut flags = String::new();
+                if prefix {
+                    flags.push('*');
+                }
+                for (bit, letter) in [(8, 'A'), (4, 'B'), (2, 'C'), (1, 'D')] {
+                    if weight & bit != 0 {
+                        flags.push(letter);
+                    }
+                }
+                if !flags.is_empty() {
+                    write!(text, ":{}", flags).ok()?;
+                }
+                Item::Operand(text)
+            }
+            2 => match r.u8()? {
+                1 => Item::Not,
+                2 => Item::Binary("&".to_string()),
+                3 => Item::Binary("|".to_string()),
+                4 => match r.i16()? {
+                    1 => Item::Binary("<->".to_string()),
+                    distance => Item::Binary(format!("<{}>", distance)),
+                },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        items.push(item);
+    }
+
+    if items.is_empty() {
+        return Some(String::new());
+    }
+    let (text, _) = render(&items, 0)?;
+    // 去掉最外层括号
+    Some(
+        text.strip_prefix("( ")
+            .and_then(|t| t.strip_suffix(" )"))
+            .map(str::to_string)
+            .unwrap_or(text),
+    )
+}
+
+fn quote_lexeme(lexeme: &str) -> String {
+    format!("'{}'", lexeme.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+/// 数组：按维度还原为嵌套 JSON 数组
+fn decode_array(buf: &[u8], decode_elem: &dyn Fn(&[u8]) -> Option<Value>) -> Option<Value> {
+    let mut r = Reader(buf);
+    let ndim = usize::try_from(r.i32()?).ok()?;
+    let _has_nulls = r.i32()?;
+    let _elem_oid = r.u32()?;
+    if ndim == 0 {
+        return Some(Value::Array(Vec::new()));
+    }
+
+    let mut dims = Vec::with_capacity(ndim);
+    for _ in 0..ndim {
+        dims.push(usize::try_from(r.i32()?).ok()?);
+        let _lower_bound = r.i32()?;
+    }
+
+    let total: usize = dims.iter().product();
+    let mut elements = Vec::with_capacity(total);
+    for _ in 0..total {
+        elements.push(match r.value()? {
+            Some(bytes) => decode_elem(bytes)?,
+            None => Value::Null,
+        });
+    }
+
+    // 从最内层维度开始逐层分组
+    for &dim in dims[1..].iter().rev() {
+        let mut grouped = Vec::with_capacity(elements.len() / dim.max(1));
+        let mut iter = elements.into_iter();
+        loop {
+            let chunk: Vec<Value> = iter.by_ref().take(dim).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            grouped.push(Value::Array(chunk));
+        }
+        elements = grouped;
+    }
+    Some(Value::Array(elements))
+}
+
+/// 范围：输出文本形式，如 [1,10)、(,2024-01-01]、empty
+fn decode_range(buf: &[u8], decode_bound: &dyn Fn(&[u8]) -> Option<Value>) -> Option<String> {
+    const EMPTY: u8 = 0x01;
+    const LB_INC: u8 = 0x02;
+    const UB_INC: u8 = 0x04;
+    const LB_INF: u8 = 0x08;
+    const UB_INF: u8 = 0x10;
+
+    let mut r = Reader(buf);
+    let flags = r.u8()?;
+    if flags & EMPTY != 0 {
+        return Some("empty".to_string());
+    }
+
+    let mut bound = |infinite: bool| -> Option<String> {
+        if infinite {
+            return Some(String::new());
+        }
+        let len = usize::try_from(r.i32()?).ok()?;
+        decode_bound(r.take(len)?).map(|value| bound_text(&value))
+    };
+    let lower = bound(flags & LB_INF != 0)?;
+    let upper = bound(flags & UB_INF != 0)?;
+
+    Some(format!(
+        "{}{},{}{}",
+        if flags & LB_INC != 0 { '[' } else { '(' },
+        lower,
+        upper,
+        if flags & UB_INC != 0 { ']' } else { ')' }
+    ))
+}
+
+fn bound_text(value: &Value) -> String {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let needs_quotes = text.is_empty()
+        || text
+            .chars()
+            .any(|c| matches!(c, ',' | '(' | ')' | '[' | ']' | '"' | '\\') || c.is_whitespace());
+    if needs_quotes {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        text
+    }
+}
+
+/// 复合类型：已知字段名时输出对象，匿名记录输出数组
+fn decode_record(buf: &[u8], fields: Option<&[(String, PgTypeInfo)]>) -> Option<Value> {
+    let mut r = Reader(buf);
+    let count = usize::try_from(r.i32()?).ok()?;
+    let mut values = Vec::with_capacity(count);
+    for i in 0..count {
+        let oid = r.u32()?;
+        let value = match r.value()? {
+            None => Value::Null,
+            Some(bytes) => match fields.and_then(|fields| fields.get(i)) {
+                Some((_, ty)) => decode(bytes, ty)?,
+                None => decode_builtin(bytes, oid).or_else(|| utf8(bytes))?,
+            },
+        };
+        values.push(value);
+    }
+
+    match fields {
+        Some(fields) if fields.len() == values.len() => Some(Value::Object(
+            fields
+                .iter()
+                .map(|(name, _)| name.clone())
+                .zip(values)
+                .collect(),
+        )),
+        _ => Some(Value::Array(values)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn numeric_bytes(weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend((digits.len() as i16).to_be_bytes());
+        buf.extend(weight.to_be_bytes());
+        buf.extend(sign.to_be_bytes());
+        buf.extend(dscale.to_be_bytes());
+        for d in digits {
+            buf.extend(d.to_be_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn test_numeric() {
+        let cases = [
+            (numeric_bytes(1, 0, 3, &[1, 2345, 6780]), "12345.678"),
+            (numeric_bytes(-1, 0, 3, &[10]), "0.001"),
+            (numeric_bytes(-2, 0, 5, &[1000]), "0.00001"),
+            (numeric_bytes(2, 0x4000, 0, &[12]), "-1200000000"),
+            (numeric_bytes(0, 0, 2, &[]), "0.00"),
+            (numeric_bytes(0, 0xC000, 0, &[]), "NaN"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(numeric(&bytes).as_deref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_temporal() {
+        assert_eq!(date(0).as_deref(), Some("2000-01-01"));
+        assert_eq!(date(i32::MAX).as_deref(), Some("infinity"));
+        assert_eq!(time(45_296_500_000).as_deref(), Some("12:34:56.500"));
+        assert_eq!(
+            timestamp(86_400_000_000, true).as_deref(),
+            Some("2000-01-02T00:00:00Z")
+        );
+        assert_eq!(
+            timestamp(-1, false).as_deref(),
+            Some("1999-12-31T23:59:59.999999")
+        );
+        assert_eq!(interval(14, 3, 3_723_500_000), "P1Y2M3DT1H2M3.5S");
+        assert_eq!(interval(0, -1, 0), "P-1D");
+        assert_eq!(interval(0, 0, 0), "PT0S");
+        assert_eq!(utc_offset(-(5 * 3600 + 30 * 60)), "-05:30");
+    }
+
+    #[test]
+    fn test_builtin_scalars() {
+        assert_eq!(decode_builtin(&[0xDE, 0xAD], oids::BYTEA), Some(json!("3q0=")));
+        assert_eq!(decode_builtin(&(-250i64).to_be_bytes(), oids::MONEY), Some(json!("-2.50")));
+        assert_eq!(decode_builtin(&f64::NAN.to_be_bytes(), oids::FLOAT8), Some(json!("NaN")));
+        assert_eq!(decode_builtin(&[2, 24, 0, 4, 10, 0, 0, 1], oids::INET), Some(json!("10.0.0.1/24")));
+        assert_eq!(decode_builtin(&[0, 0, 0, 3, 0b1010_0000], oids::VARBIT), Some(json!("101")));
+        assert_eq!(decode_builtin(b"\x01{\"a\":1}", oids::JSONB), Some(json!({"a": 1})));
+    }
+
+    #[test]
+    fn test_array_and_range() {
+        // int4[][]：{{1,2},{3,NULL}}
+        let mut buf = Vec::new();
+        for v in [2i32, 1, 23, 2, 1, 2, 1] {
+            buf.extend(v.to_be_bytes());
+        }
+        for v in [Some(1i32), Some(2), Some(3), None] {
+            match v {
+                Some(v) => {
+                    buf.extend(4i32.to_be_bytes());
+                    buf.extend(v.to_be_bytes());
+                }
+                None => buf.extend((-1i32).to_be_bytes()),
+            }
+        }
+        let value = decode_array(&buf, &|bytes| decode_builtin(bytes, oids::INT4));
+        assert_eq!(value, Some(json!([[1, 2], [3, null]])));
+
+        // int4range：[1,10)
+        let mut buf = vec![0x02];
+        for v in [1i32, 10] {
+            buf.extend(4i32.to_be_bytes());
+            buf.extend(v.to_be_bytes());
+        }
+        assert_eq!(decode_builtin(&buf, oids::INT4RANGE), Some(json!("[1,10)")));
+        assert_eq!(decode_builtin(&[0x01], oids::INT4RANGE), Some(json!("empty")));
+    }
+}
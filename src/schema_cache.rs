@@ -0,0 +1,471 @@
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sqlx::postgres::PgListener;
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::error::Result;
+
+/// 主数据库（DATABASE_URL）在缓存中使用的 ID，租户数据库沿用 POOL_MANAGER 中的 ID
+pub const MAIN_DATABASE_ID: i32 = 0;
+
+/// 触发结构缓存刷新的通知频道（NOTIFY crestrail_reload_schema）
+pub const SCHEMA_RELOAD_CHANNEL: &str = "crestrail_reload_schema";
+
+/// 表未命中时两次重新加载之间的最小间隔（SCHEMA_MISS_RELOAD_INTERVAL，秒，默认 10），
+/// 间隔内的未命中直接按表不存在处理，结构变更依赖 NOTIFY 刷新
+static MISS_RELOAD_INTERVAL: Lazy<Duration> = Lazy::new(|| {
+    let secs = std::env::var("SCHEMA_MISS_RELOAD_INTERVAL")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10);
+    Duration::from_secs(secs)
+});
+
+/// 全局数据库结构缓存
+pub static SCHEMA_CACHE: Lazy<SchemaCache> = Lazy::new(SchemaCache::new);
+
+/// 表或视图
+#[derive(Debug, Clone, Serialize)]
+pub struct TableInfo {
+    pub schema: String,
+    pub name: String,
+    /// table / partitioned_table / view / materialized_view / foreign_table
+    pub kind: String,
+    /// 按定义顺序排列的列
+    pub columns: Vec<ColumnInfo>,
+    /// 主键列（按主键定义顺序），视图为空
+    pub primary_key: Vec<String>,
+}
+
+impl TableInfo {
+    /// 是否为视图（包括物化视图）
+    pub fn is_view(&self) -> bool {
+        matches!(self.kind.as_str(), "view" | "materialized_view")
+    }
+}
+
+/// 列
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    /// 列类型（format_type 格式，不含长度/精度修饰，可直接用于 CAST）
+    pub data_type: String,
+    pub nullable: bool,
+    pub has_default: bool,
+    /// 生成列（GENERATED ALWAYS AS (...) STORED），不能写入
+    pub generated: bool,
+    /// 标识列：always / by default，普通列为 None
+    pub identity: Option<String>,
+}
+
+impl ColumnInfo {
+    /// 是否为 GENERATED ALWAYS AS IDENTITY（只能在 OVERRIDING SYSTEM VALUE 时显式插入，不能更新）
+    pub fn is_identity_always(&self) -> bool {
+        self.identity.as_deref() == Some("always")
+    }
+}
+
+/// 外键关系（一条外键约束，列按约束定义顺序一一对应）
+#[derive(Debug, Clone, Serialize)]
+pub struct Relationship {
+    pub constraint_name: String,
+    /// 引用方（持有外键的表）
+    pub table_schema: String,
+    pub table_name: String,
+    pub columns: Vec<String>,
+    /// 被引用方
+    pub foreign_schema: String,
+    pub foreign_table: String,
+    pub foreign_columns: Vec<String>,
+}
+
+/// 表的关系元数据
+#[derive(Debug, Clone, Default)]
+pub struct TableRelationships {
+    /// 该表引用的其他表（多对一）
+    pub foreign_keys: Vec<Relationship>,
+    /// 引用该表的其他表（一对多）
+    pub referenced_by: Vec<Relationship>,
+}
+
+/// 函数
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionInfo {
+    pub schema: String,
+    pub name: String,
+    /// 参数列表，例如 "user_id integer, active boolean"
+    pub arguments: String,
+    pub return_type: String,
+}
+
+/// 单个数据库的结构快照
+#[derive(Debug, Default)]
+pub struct DatabaseSchema {
+    tables: HashMap<(String, String), TableInfo>,
+    relationships: Vec<Relationship>,
+    functions: Vec<FunctionInfo>,
+}
+
+impl DatabaseSchema {
+    /// 查找表或视图
+    pub fn table(&self, schema: &str, table: &str) -> Option<&TableInfo> {
+        self.tables.get(&(schema.to_string(), table.to_string()))
+    }
+
+    /// 所有表和视图（无序）
+    pub fn tables(&self) -> impl Iterator<Item = &TableInfo> {
+        self.tables.values()
+    }
+
+    /// 表中各列的类型
+    pub fn column_types(&self, schema: &str, table: &str) -> Option<HashMap<String, String>> {
+        self.table(schema, table).map(|info| {
+            info.columns
+                .iter()
+                .map(|column| (column.name.clone(), column.data_type.clone()))
+                .collect()
+        })
+    }
+
+    /// 表的外键关系（包括引用和被引用两个方向，自引用外键同时出现在两侧）
+    pub fn relationships(&self, schema: &str, table: &str) -> TableRelationships {
+        let mut relationships = TableRelationships::default();
+
+        for rel in &self.relationships {
+            if rel.table_schema == schema && rel.table_name == table {
+                relationships.foreign_keys.push(rel.clone());
+            }
+            if rel.foreign_schema == schema && rel.foreign_table == table {
+                relationships.referenced_by.push(rel.clone());
+            }
+        }
+
+        relationships
+    }
+
+    /// 缓存内容统计
+    pub fn summary(&self) -> serde_json::Value {
+        let views = self.tables.values().filter(|info| info.is_view()).count();
+        serde_json::json!({
+            "tables": self.tables.len() - views,
+            "views": views,
+            "relationships": self.relationships.len(),
+            "functions": self.functions.len(),
+        })
+    }
+}
+
+/// 数据库结构缓存（按数据库 ID 区分）
+pub struct SchemaCache {
+    databases: DashMap<i32, Arc<DatabaseSchema>>,
+    listeners: DashMap<i32, JoinHandle<()>>,
+    /// 各数据库最近一次加载（或因未命中尝试加载）的时间
+    reloaded_at: DashMap<i32, Instant>,
+}
+
+impl SchemaCache {
+    pub fn new() -> Self {
+        Self {
+            databases: DashMap::new(),
+            listeners: DashMap::new(),
+            reloaded_at: DashMap::new(),
+        }
+    }
+
+    /// 连接池创建后调用：加载结构并监听刷新通知
+    pub async fn initialize(&'static self, pool: &PgPool, database_id: i32) {
+        if let Err(e) = self.reload(pool, database_id).await {
+            // 加载失败不影响连接池使用，首次访问时会再次尝试
+            tracing::warn!("加载数据库结构失败: database_id={}, {}", database_id, e);
+        }
+        self.watch(pool.clone(), database_id);
+    }
+
+    /// 获取数据库结构，未缓存时加载
+    pub async fn get(&self, pool: &PgPool, database_id: i32) -> Result<Arc<DatabaseSchema>> {
+        match self.databases.get(&database_id) {
+            Some(snapshot) => Ok(snapshot.clone()),
+            None => self.reload(pool, database_id).await,
+        }
+    }
+
+    /// 获取包含指定表的结构快照，表不存在时重新加载一次（表可能在缓存之后才创建）。
+    /// 未命中触发的加载受最小间隔限制，避免请求不存在的表放大系统目录查询
+    pub async fn for_table(
+        &self,
+        pool: &PgPool,
+        database_id: i32,
+        schema: &str,
+        table: &str,
+    ) -> Result<Arc<DatabaseSchema>> {
+        let snapshot = self.get(pool, database_id).await?;
+        if snapshot.table(schema, table).is_some() || !self.claim_miss_reload(database_id) {
+            return Ok(snapshot);
+        }
+        self.reload(pool, database_id).await
+    }
+
+    /// 距上次加载已超过最小间隔时记录本次加载并返回 true，并发的未命中只有一个会加载
+    fn claim_miss_reload(&self, database_id: i32) -> bool {
+        match self.reloaded_at.entry(database_id) {
+            Entry::Occupied(entry) if entry.get().elapsed() < *MISS_RELOAD_INTERVAL => false,
+            Entry::Occupied(mut entry) => {
+                entry.insert(Instant::now());
+                true
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(Instant::now());
+                true
+            }
+        }
+    }
+
+    /// 重新加载数据库结构
+    pub async fn reload(&self, pool: &PgPool, database_id: i32) -> Result<Arc<DatabaseSchema>> {
+        let snapshot = Arc::new(load_schema(pool).await?);
+        self.databases.insert(database_id, snapshot.clone());
+        self.reloaded_at.insert(database_id, Instant::now());
+        tracing::info!("数据库结构缓存已刷新: database_id={}", database_id);
+        Ok(snapshot)
+    }
+
+    /// 移除数据库的缓存并停止监听（连接池关闭时调用）
+    pub fn remove(&self, database_id: i32) {
+        self.databases.remove(&database_id);
+        self.reloaded_at.remove(&database_id);
+        if let Some((_, handle)) = self.listeners.remove(&database_id) {
+            handle.abort();
+        }
+    }
+
+    /// 在后台监听 crestrail_reload_schema 通知，收到后刷新缓存
+    fn watch(&'static self, pool: PgPool, database_id: i32) {
+        let handle = tokio::spawn(async move {
+            // initialize 刚加载过结构，首次建立监听时无需再次加载
+            let mut reconnect = false;
+            while !pool.is_closed() {
+                match self.listen(&pool, database_id, reconnect).await {
+                    Ok(()) => tracing::warn!("结构刷新监听连接断开: database_id={}", database_id),
+                    Err(e) => tracing::warn!("结构刷新监听中断: database_id={}, {}", database_id, e),
+                }
+                reconnect = true;
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+
+        if let Some(previous) = self.listeners.insert(database_id, handle) {
+            previous.abort();
+        }
+    }
+
+    /// 监听直到连接断开。断开期间发出的通知会丢失，因此重新建立监听后先刷新一次缓存
+    async fn listen(&self, pool: &PgPool, database_id: i32, reconnect: bool) -> Result<()> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(SCHEMA_RELOAD_CHANNEL).await?;
+
+        if reconnect {
+            self.reload(pool, database_id).await?;
+        }
+
+        loop {
+            // try_recv 在连接断开时返回 None（recv 会静默重连并丢失期间的通知），
+            // 此时交由 watch 重新建立监听并刷新
+            if listener.try_recv().await?.is_none() {
+                return Ok(());
+            }
+
+            // 一次迁移通常包含多条 DDL，合并短时间内的连续通知
+            while let Ok(notification) =
+                tokio::time::timeout(Duration::from_millis(200), listener.try_recv()).await
+            {
+                if notification?.is_none() {
+                    return Ok(());
+                }
+            }
+
+            self.reload(pool, database_id).await?;
+        }
+    }
+}
+
+impl Default for SchemaCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 从系统目录读取表、视图、列、主键、外键和函数
+async fn load_schema(pool: &PgPool) -> Result<DatabaseSchema> {
+    let mut schema = DatabaseSchema::default();
+
+    let column_rows = sqlx::query(
+        r#"
+        SELECT
+            n.nspname::text AS table_schema,
+            c.relname::text AS table_name,
+            CASE c.relkind
+                WHEN 'r' THEN 'table'
+                WHEN 'p' THEN 'partitioned_table'
+                WHEN 'v' THEN 'view'
+                WHEN 'm' THEN 'materialized_view'
+                ELSE 'foreign_table'
+            END AS kind,
+            a.attname::text AS column_name,
+            format_type(a.atttypid, NULL) AS data_type,
+            NOT a.attnotnull AS nullable,
+            a.atthasdef AS has_default,
+            a.attgenerated <> '' AS generated,
+            CASE a.attidentity WHEN 'a' THEN 'always' WHEN 'd' THEN 'by default' END AS identity
+        FROM pg_class c
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+        WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+            AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+            AND n.nspname NOT LIKE 'pg_toast%'
+        ORDER BY n.nspname, c.relname, a.attnum
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for row in column_rows {
+        let key: (String, String) = (row.get("table_schema"), row.get("table_name"));
+        let table = schema
+            .tables
+            .entry(key.clone())
+            .or_insert_with(|| TableInfo {
+                schema: key.0,
+                name: key.1,
+                kind: row.get("kind"),
+                columns: Vec::new(),
+                primary_key: Vec::new(),
+            });
+        table.columns.push(ColumnInfo {
+            name: row.get("column_name"),
+            data_type: row.get("data_type"),
+            nullable: row.get("nullable"),
+            has_default: row.get("has_default"),
+            generated: row.get("generated"),
+            identity: row.get("identity"),
+        });
+    }
+
+    let primary_key_rows = sqlx::query(
+        r#"
+        SELECT
+            n.nspname::text AS table_schema,
+            c.relname::text AS table_name,
+            array_agg(a.attname::text ORDER BY k.ord) AS columns
+        FROM pg_index i
+        JOIN pg_class c ON c.oid = i.indrelid
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        CROSS JOIN LATERAL unnest(i.indkey) WITH ORDINALITY AS k(attnum, ord)
+        JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = k.attnum
+        WHERE i.indisprimary
+            AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+        GROUP BY n.nspname, c.relname
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for row in primary_key_rows {
+        let key: (String, String) = (row.get("table_schema"), row.get("table_name"));
+        if let Some(table) = schema.tables.get_mut(&key) {
+            table.primary_key = row.get("columns");
+        }
+    }
+
+    // 使用 pg_constraint 以保证复合外键的列一一对应
+    let relationship_rows = sqlx::query(
+        r#"
+        SELECT
+            c.conname::text AS constraint_name,
+            sn.nspname::text AS table_schema,
+            s.relname::text AS table_name,
+            tn.nspname::text AS foreign_schema,
+            t.relname::text AS foreign_table,
+            array_agg(sa.attname::text ORDER BY k.ord) AS columns,
+            array_agg(ta.attname::text ORDER BY k.ord) AS foreign_columns
+        FROM pg_constraint c
+        JOIN pg_class s ON s.oid = c.conrelid
+        JOIN pg_namespace sn ON sn.oid = s.relnamespace
+        JOIN pg_class t ON t.oid = c.confrelid
+        JOIN pg_namespace tn ON tn.oid = t.relnamespace
+        CROSS JOIN LATERAL unnest(c.conkey, c.confkey) WITH ORDINALITY AS k(col, fcol, ord)
+        JOIN pg_attribute sa ON sa.attrelid = c.conrelid AND sa.attnum = k.col
+        JOIN pg_attribute ta ON ta.attrelid = c.confrelid AND ta.attnum = k.fcol
+        WHERE c.contype = 'f'
+        GROUP BY c.conname, sn.nspname, s.relname, tn.nspname, t.relname
+        ORDER BY c.conname
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    schema.relationships = relationship_rows
+        .iter()
+        .map(|row| Relationship {
+            constraint_name: row.get("constraint_name"),
+            table_schema: row.get("table_schema"),
+            table_name: row.get("table_name"),
+            columns: row.get("columns"),
+            foreign_schema: row.get("foreign_schema"),
+            foreign_table: row.get("foreign_table"),
+            foreign_columns: row.get("foreign_columns"),
+        })
+        .collect();
+
+    let function_rows = sqlx::query(
+        r#"
+        SELECT
+            n.nspname::text AS function_schema,
+            p.proname::text AS function_name,
+            pg_get_function_identity_arguments(p.oid) AS arguments,
+            pg_get_function_result(p.oid) AS return_type
+        FROM pg_proc p
+        JOIN pg_namespace n ON n.oid = p.pronamespace
+        WHERE p.prokind = 'f'
+            AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+            AND n.nspname NOT LIKE 'pg_toast%'
+        ORDER BY n.nspname, p.proname
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    schema.functions = function_rows
+        .iter()
+        .map(|row| FunctionInfo {
+            schema: row.get("function_schema"),
+            name: row.get("function_name"),
+            arguments: row.get("arguments"),
+            return_type: row.get::<Option<String>, _>("return_type").unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_miss_reload_is_throttled() {
+        let cache = SchemaCache::new();
+        assert!(cache.claim_miss_reload(1));
+        // 间隔内的未命中不再加载，其他数据库不受影响
+        assert!(!cache.claim_miss_reload(1));
+        assert!(cache.claim_miss_reload(2));
+
+        cache.remove(1);
+        assert!(cache.claim_miss_reload(1));
+    }
+}
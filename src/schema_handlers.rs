@@ -1,4 +1,9 @@
-use crate::error::Result;
+use crate::error::{AppError, Result};
+use crate::query_builder::{Embed, EmbedJoin, QueryParams, SelectItem};
+use crate::pool_manager::DatabaseId;
+use crate::schema_cache::{
+    DatabaseSchema, Relationship, TableRelationships, MAIN_DATABASE_ID, SCHEMA_CACHE,
+};
 use axum::{
     extract::{Path, State},
     Extension, Json,
@@ -6,6 +11,7 @@ use axum::{
 use serde::Serialize;
 use serde_json::Value;
 use sqlx::{PgPool, Row};
+use std::collections::{BTreeMap, HashMap};
 
 /// Schema 信息
 #[derive(Debug, Serialize)]
@@ -83,29 +89,24 @@ pub struct TableStructure {
 pub async fn list_schemas(
     State(main_pool): State<PgPool>,
     dynamic_pool: Option<Extension<PgPool>>,
+    database_id: Option<Extension<DatabaseId>>,
 ) -> Result<Json<Vec<SchemaInfo>>> {
     // 尝试使用动态连接池，如果没有则使用主连接池
     let pool = dynamic_pool.as_deref().unwrap_or(&main_pool);
-    
-    let schemas = sqlx::query(
-        r#"
-        SELECT 
-            table_schema as schema_name,
-            COUNT(table_name) as table_count
-        FROM information_schema.tables
-        WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
-        GROUP BY table_schema
-                ORDER BY table_schema
-                "#,
-            )
-            .fetch_all(pool)
-            .await?;
+    let database_id = database_id.map_or(MAIN_DATABASE_ID, |Extension(DatabaseId(id))| id);
 
-    let result: Vec<SchemaInfo> = schemas
-        .iter()
-        .map(|row| SchemaInfo {
-            schema_name: row.get("schema_name"),
-            table_count: row.get("table_count"),
+    // 从结构缓存统计各 schema 的表和视图数
+    let catalog = SCHEMA_CACHE.get(pool, database_id).await?;
+    let mut counts: BTreeMap<&str, i64> = BTreeMap::new();
+    for info in catalog.tables() {
+        *counts.entry(info.schema.as_str()).or_default() += 1;
+    }
+
+    let result: Vec<SchemaInfo> = counts
+        .into_iter()
+        .map(|(schema_name, table_count)| SchemaInfo {
+            schema_name: schema_name.to_string(),
+            table_count,
         })
         .collect();
 
@@ -117,42 +118,70 @@ pub async fn list_tables(
     State(main_pool): State<PgPool>,
     Path(schema): Path<String>,
     dynamic_pool: Option<Extension<PgPool>>,
+    database_id: Option<Extension<DatabaseId>>,
 ) -> Result<Json<Vec<TableInfo>>> {
     let pool = dynamic_pool.as_deref().unwrap_or(&main_pool);
-    
-    let tables = sqlx::query(
+    let database_id = database_id.map_or(MAIN_DATABASE_ID, |Extension(DatabaseId(id))| id);
+
+    let catalog = SCHEMA_CACHE.get(pool, database_id).await?;
+
+    // 行数估算和表大小随数据变化，不进入结构缓存，直接从 pg_class 读取
+    let stats = sqlx::query(
         r#"
-        SELECT 
-            t.table_name,
-            t.table_type,
-            pg_class.reltuples::bigint as row_count,
-            pg_size_pretty(pg_total_relation_size(quote_ident(t.table_schema)||'.'||quote_ident(t.table_name))) as size
-        FROM information_schema.tables t
-        LEFT JOIN pg_class ON pg_class.relname = t.table_name
-        LEFT JOIN pg_namespace ON pg_namespace.nspname = t.table_schema 
-            AND pg_class.relnamespace = pg_namespace.oid
-        WHERE t.table_schema = $1
-        ORDER BY t.table_name
+        SELECT
+            c.relname::text AS table_name,
+            c.reltuples::bigint AS row_count,
+            pg_size_pretty(pg_total_relation_size(c.oid)) AS size
+        FROM pg_class c
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
         "#,
-            )
-            .bind(&schema)
-            .fetch_all(pool)
-            .await?;
+    )
+    .bind(&schema)
+    .fetch_all(pool)
+    .await?;
 
-    let result: Vec<TableInfo> = tables
+    let mut stats: HashMap<String, (Option<i64>, Option<String>)> = stats
         .iter()
-        .map(|row| TableInfo {
-            table_name: row.get("table_name"),
-            table_type: row.get("table_type"),
-            row_count: row.try_get("row_count").ok(),
-            size: row.try_get("size").ok(),
+        .map(|row| {
+            (
+                row.get("table_name"),
+                (row.try_get("row_count").ok(), row.try_get("size").ok()),
+            )
+        })
+        .collect();
+
+    let mut result: Vec<TableInfo> = catalog
+        .tables()
+        .filter(|info| info.schema == schema)
+        .map(|info| {
+            let (row_count, size) = stats.remove(&info.name).unwrap_or_default();
+            TableInfo {
+                table_name: info.name.clone(),
+                table_type: table_type(&info.kind).to_string(),
+                row_count,
+                size,
+            }
         })
         .collect();
+    result.sort_by(|a, b| a.table_name.cmp(&b.table_name));
 
     Ok(Json(result))
 }
 
+/// 结构缓存中的表类型对应的 information_schema 写法
+fn table_type(kind: &str) -> &'static str {
+    match kind {
+        "view" => "VIEW",
+        "materialized_view" => "MATERIALIZED VIEW",
+        "foreign_table" => "FOREIGN",
+        _ => "BASE TABLE",
+    }
+}
+
 /// GET /api/schema/:schema/table/:table/structure - 获取表结构详情
+///
+/// 列默认值、长度/精度、约束和索引不在结构缓存中，仍直接查询系统目录
 pub async fn get_table_structure(
     State(main_pool): State<PgPool>,
     Path((schema, table)): Path<(String, String)>,
@@ -338,89 +367,204 @@ pub async fn get_table_structure(
     }))
 }
 
+/// 为游标分页补全排序键（order 列 + 主键），要求 params.column_types 已填充
+pub fn prepare_cursor(
+    catalog: &DatabaseSchema,
+    schema: &str,
+    table: &str,
+    params: &mut QueryParams,
+) -> Result<()> {
+    if params.cursor.is_none() {
+        return Ok(());
+    }
+
+    let info = catalog.table(schema, table);
+    let primary_key = info.map(|info| info.primary_key.clone()).unwrap_or_default();
+    params.prepare_keyset(&primary_key)?;
+
+    // 行值比较会跳过排序键为 NULL 的行，因此在查询前拒绝可为 NULL 的排序列
+    for order in &params.order_by {
+        let column = info
+            .and_then(|info| info.columns.iter().find(|column| column.name == order.column))
+            .ok_or_else(|| AppError::InvalidQuery(format!("列不存在: {}", order.column)))?;
+        if column.nullable {
+            return Err(AppError::InvalidQuery(format!(
+                "游标分页的排序列 {} 允许 NULL 值，请改用 NOT NULL 列排序",
+                order.column
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// upsert 未指定 on_conflict 时以主键作为冲突列
+pub fn prepare_upsert(
+    catalog: &DatabaseSchema,
+    schema: &str,
+    table: &str,
+    params: &mut QueryParams,
+) -> Result<()> {
+    if params.on_conflict.is_some() {
+        return Ok(());
+    }
+
+    let primary_key = catalog
+        .table(schema, table)
+        .map(|info| info.primary_key.clone())
+        .unwrap_or_default();
+    if primary_key.is_empty() {
+        return Err(AppError::InvalidQuery(format!(
+            "表 {}.{} 没有主键，请使用 on_conflict 指定冲突列",
+            schema, table
+        )));
+    }
+    params.on_conflict = Some(primary_key);
+
+    Ok(())
+}
+
+/// 根据外键元数据解析 select 中的嵌入资源并填充其列类型（递归处理嵌套嵌入）
+pub fn resolve_embeds(
+    catalog: &DatabaseSchema,
+    schema: &str,
+    table: &str,
+    params: &mut QueryParams,
+) -> Result<()> {
+    let select = match params.select.as_mut() {
+        Some(select) if select.iter().any(|item| matches!(item, SelectItem::Embed(_))) => select,
+        _ => return Ok(()),
+    };
+
+    let relationships = catalog.relationships(schema, table);
+
+    for item in select.iter_mut() {
+        if let SelectItem::Embed(embed) = item {
+            let join = find_embed_join(&relationships, table, embed)?;
+            let embed_schema = join.schema.clone();
+            embed.join = Some(join);
+            embed.params.column_types = catalog
+                .column_types(&embed_schema, &embed.table)
+                .unwrap_or_default();
+            resolve_embeds(catalog, &embed_schema, &embed.table, &mut embed.params)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 在父表的外键关系中查找嵌入资源对应的关联方式
+fn find_embed_join(
+    relationships: &TableRelationships,
+    table: &str,
+    embed: &Embed,
+) -> Result<EmbedJoin> {
+    let matches_hint = |rel: &Relationship| match &embed.hint {
+        Some(hint) => rel.constraint_name == *hint || rel.columns.contains(hint),
+        None => true,
+    };
+    // 自引用外键同时出现在两个方向：指定提示时按多对一（父行）关联，.inverse 按一对多（子行）关联
+    let self_reference = |rel: &Relationship| {
+        rel.table_schema == rel.foreign_schema && rel.table_name == rel.foreign_table
+    };
+
+    let mut candidates = Vec::new();
+
+    // 多对一：父表的外键指向嵌入表
+    for rel in &relationships.foreign_keys {
+        if rel.foreign_table == embed.table && matches_hint(rel) && !embed.inverse {
+            candidates.push(EmbedJoin {
+                schema: rel.foreign_schema.clone(),
+                to_many: false,
+                columns: rel
+                    .foreign_columns
+                    .iter()
+                    .cloned()
+                    .zip(rel.columns.iter().cloned())
+                    .collect(),
+            });
+        }
+    }
+
+    // 一对多：嵌入表的外键指向父表
+    for rel in &relationships.referenced_by {
+        let forward_only = embed.hint.is_some() && !embed.inverse && self_reference(rel);
+        if rel.table_name == embed.table && matches_hint(rel) && !forward_only {
+            candidates.push(EmbedJoin {
+                schema: rel.table_schema.clone(),
+                to_many: true,
+                columns: rel
+                    .columns
+                    .iter()
+                    .cloned()
+                    .zip(rel.foreign_columns.iter().cloned())
+                    .collect(),
+            });
+        }
+    }
+
+    match candidates.len() {
+        0 => Err(AppError::InvalidQuery(format!(
+            "找不到 {} 与 {} 之间的外键关系",
+            table, embed.table
+        ))),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(AppError::InvalidQuery(format!(
+            "{} 与 {} 之间存在多条外键关系，请使用 {}!<约束名或外键列>(...) 指定（反向关联加 .inverse 后缀）",
+            table, embed.table, embed.table
+        ))),
+    }
+}
+
 /// GET /api/schema/:schema/table/:table/relationships - 获取表的关系图数据
 pub async fn get_table_relationships(
     State(main_pool): State<PgPool>,
     Path((schema, table)): Path<(String, String)>,
     dynamic_pool: Option<Extension<PgPool>>,
+    database_id: Option<Extension<DatabaseId>>,
 ) -> Result<Json<Value>> {
     let pool = dynamic_pool.as_deref().unwrap_or(&main_pool);
-    
-    // 获取该表引用的其他表（外键）
-    let foreign_keys = sqlx::query(
-        r#"
-        SELECT
-            tc.constraint_name,
-            kcu.column_name,
-            ccu.table_schema AS foreign_schema,
-            ccu.table_name AS foreign_table,
-            ccu.column_name AS foreign_column
-        FROM information_schema.table_constraints AS tc
-        JOIN information_schema.key_column_usage AS kcu
-            ON tc.constraint_name = kcu.constraint_name
-            AND tc.table_schema = kcu.table_schema
-        JOIN information_schema.constraint_column_usage AS ccu
-            ON ccu.constraint_name = tc.constraint_name
-            AND ccu.table_schema = tc.table_schema
-        WHERE tc.constraint_type = 'FOREIGN KEY'
-            AND tc.table_schema = $1
-            AND tc.table_name = $2
-        "#,
-            )
-            .bind(&schema)
-            .bind(&table)
-            .fetch_all(pool)
-            .await?;
+    let database_id = database_id.map_or(MAIN_DATABASE_ID, |Extension(DatabaseId(id))| id);
 
-    // 获取引用该表的其他表（被引用）
-    let referenced_by = sqlx::query(
-        r#"
-        SELECT
-            tc.table_schema,
-            tc.table_name,
-            tc.constraint_name,
-            kcu.column_name,
-            ccu.column_name AS foreign_column
-        FROM information_schema.table_constraints AS tc
-        JOIN information_schema.key_column_usage AS kcu
-            ON tc.constraint_name = kcu.constraint_name
-            AND tc.table_schema = kcu.table_schema
-        JOIN information_schema.constraint_column_usage AS ccu
-            ON ccu.constraint_name = tc.constraint_name
-            AND ccu.table_schema = tc.table_schema
-        WHERE tc.constraint_type = 'FOREIGN KEY'
-            AND ccu.table_schema = $1
-            AND ccu.table_name = $2
-        "#,
-            )
-            .bind(&schema)
-            .bind(&table)
-            .fetch_all(pool)
-            .await?;
+    let catalog = SCHEMA_CACHE.for_table(pool, database_id, &schema, &table).await?;
+    let relationships = catalog.relationships(&schema, &table);
 
-    let fk_list: Vec<Value> = foreign_keys
+    // 每个列对输出一条记录
+    let fk_list: Vec<Value> = relationships
+        .foreign_keys
         .iter()
-        .map(|row| {
-            serde_json::json!({
-                "constraint_name": row.get::<String, _>("constraint_name"),
-                "column": row.get::<String, _>("column_name"),
-                "foreign_schema": row.get::<String, _>("foreign_schema"),
-                "foreign_table": row.get::<String, _>("foreign_table"),
-                "foreign_column": row.get::<String, _>("foreign_column"),
-            })
+        .flat_map(|rel| {
+            rel.columns
+                .iter()
+                .zip(&rel.foreign_columns)
+                .map(move |(column, foreign_column)| {
+                    serde_json::json!({
+                        "constraint_name": rel.constraint_name,
+                        "column": column,
+                        "foreign_schema": rel.foreign_schema,
+                        "foreign_table": rel.foreign_table,
+                        "foreign_column": foreign_column,
+                    })
+                })
         })
         .collect();
 
-    let ref_list: Vec<Value> = referenced_by
+    let ref_list: Vec<Value> = relationships
+        .referenced_by
         .iter()
-        .map(|row| {
-            serde_json::json!({
-                "schema": row.get::<String, _>("table_schema"),
-                "table": row.get::<String, _>("table_name"),
-                "constraint_name": row.get::<String, _>("constraint_name"),
-                "column": row.get::<String, _>("column_name"),
-                "foreign_column": row.get::<String, _>("foreign_column"),
-            })
+        .flat_map(|rel| {
+            rel.columns
+                .iter()
+                .zip(&rel.foreign_columns)
+                .map(move |(column, foreign_column)| {
+                    serde_json::json!({
+                        "schema": rel.table_schema,
+                        "table": rel.table_name,
+                        "constraint_name": rel.constraint_name,
+                        "column": column,
+                        "foreign_column": foreign_column,
+                    })
+                })
         })
         .collect();
 
@@ -430,3 +574,52 @@ pub async fn get_table_relationships(
     })))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embed(select: &str) -> Embed {
+        let query = [("select".to_string(), format!("id,{}", select))];
+        let params = QueryParams::from_query_map(query.into_iter().collect()).unwrap();
+        match params.select.unwrap().remove(1) {
+            SelectItem::Embed(embed) => *embed,
+            other => panic!("unexpected item: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_self_referential_embed() {
+        let rel = Relationship {
+            constraint_name: "fk_categories_parent".to_string(),
+            table_schema: "public".to_string(),
+            table_name: "categories".to_string(),
+            columns: vec!["parent_id".to_string()],
+            foreign_schema: "public".to_string(),
+            foreign_table: "categories".to_string(),
+            foreign_columns: vec!["id".to_string()],
+        };
+        let relationships = TableRelationships {
+            foreign_keys: vec![rel.clone()],
+            referenced_by: vec![rel],
+        };
+        let join = |select: &str| find_embed_join(&relationships, "categories", &embed(select));
+
+        // 不带提示时两个方向都匹配
+        assert!(join("categories(*)").is_err());
+
+        // 约束名或外键列：父行（多对一）
+        for select in ["categories!fk_categories_parent(*)", "categories!parent_id(*)"] {
+            let parent = join(select).unwrap();
+            assert!(!parent.to_many);
+            assert_eq!(parent.columns, vec![("id".to_string(), "parent_id".to_string())]);
+        }
+
+        // .inverse：子行（一对多）
+        let children = join("categories!fk_categories_parent.inverse(*)").unwrap();
+        assert!(children.to_many);
+        assert_eq!(children.columns, vec![("parent_id".to_string(), "id".to_string())]);
+
+        let query = [("select".to_string(), "id,categories!parent_id.reverse(*)".to_string())];
+        assert!(QueryParams::from_query_map(query.into_iter().collect()).is_err());
+    }
+}
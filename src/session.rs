@@ -0,0 +1,480 @@
+//! 交互式事务
+//!
+//! POST /transaction/begin 开启事务并固定一个连接，之后带 X-Transaction-Id 的 REST 请求都在这个连接上执行，
+//! 直到调用 commit 或 rollback。空闲超时的事务由后台任务自动回滚；每个用户同时打开的事务数有上限，
+//! 避免连接池被长期占用。
+
+use crate::auth::Claims;
+use crate::error::{AppError, Result};
+use crate::transaction::TransactionOptions;
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{Extension, FromRequestParts, Path, State},
+    http::{request::Parts, StatusCode},
+    Json,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, PgConnection, PgPool, Postgres, Transaction};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedMappedMutexGuard, OwnedMutexGuard};
+
+/// 指定交互式事务的请求头
+pub const TRANSACTION_ID_HEADER: &str = "x-transaction-id";
+
+/// 交互式事务的空闲超时（TRANSACTION_IDLE_TIMEOUT，秒，默认 30）
+static IDLE_TIMEOUT: Lazy<Duration> = Lazy::new(|| {
+    let secs = std::env::var("TRANSACTION_IDLE_TIMEOUT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(30);
+    Duration::from_secs(secs)
+});
+
+/// 每个用户同时打开的交互式事务数上限（TRANSACTION_MAX_PER_USER，默认 2）
+static MAX_PER_USER: Lazy<usize> = Lazy::new(|| {
+    std::env::var("TRANSACTION_MAX_PER_USER")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|max| *max > 0)
+        .unwrap_or(2)
+});
+
+/// 所有打开的交互式事务
+pub static SESSIONS: Lazy<SessionRegistry> = Lazy::new(SessionRegistry::default);
+
+type PinnedTransaction = Transaction<'static, Postgres>;
+
+/// 一个打开的交互式事务
+struct Session {
+    /// 开启事务的用户 ID
+    owner: i32,
+    /// 事务结束后为 None；请求执行期间持有锁，同一事务上的请求依次执行
+    tx: Arc<Mutex<Option<PinnedTransaction>>>,
+    /// 最近一次使用的时间
+    last_used: std::sync::Mutex<Instant>,
+}
+
+impl Session {
+    fn touch(&self) {
+        if let Ok(mut last_used) = self.last_used.lock() {
+            *last_used = Instant::now();
+        }
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used
+            .lock()
+            .map(|last_used| last_used.elapsed())
+            .unwrap_or_default()
+    }
+}
+
+/// 交互式事务注册表
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: DashMap<String, Arc<Session>>,
+    /// 用户 ID -> 打开的事务数
+    open_per_user: DashMap<i32, usize>,
+}
+
+impl SessionRegistry {
+    /// 开启事务，返回事务 ID
+    async fn begin(&self, pool: &PgPool, owner: i32, options: &TransactionOptions) -> Result<String> {
+        // 先占用名额再开启事务，并发的 begin 不会超过上限
+        let slot = self.reserve(owner)?;
+
+        let mut tx = options.begin(pool).await?;
+        let id: String = sqlx::query_scalar("SELECT gen_random_uuid()::text")
+            .fetch_one(&mut *tx)
+            .await?;
+
+        self.insert(id.clone(), owner, Some(tx));
+        slot.keep();
+        Ok(id)
+    }
+
+    /// 占用一个用户事务名额
+    fn reserve(&self, owner: i32) -> Result<SlotGuard<'_>> {
+        let mut open = self.open_per_user.entry(owner).or_insert(0);
+        if *open >= *MAX_PER_USER {
+            return Err(AppError::TooManyRequests(format!(
+                "每个用户最多同时打开 {} 个交互式事务，请先提交或回滚",
+                *MAX_PER_USER
+            )));
+        }
+        *open += 1;
+
+        Ok(SlotGuard {
+            registry: self,
+            owner,
+            kept: false,
+        })
+    }
+
+    fn insert(&self, id: String, owner: i32, tx: Option<PinnedTransaction>) {
+        let session = Session {
+            owner,
+            tx: Arc::new(Mutex::new(tx)),
+            last_used: std::sync::Mutex::new(Instant::now()),
+        };
+        self.sessions.insert(id, Arc::new(session));
+    }
+
+    /// 查找事务并检查是否属于当前用户
+    fn session(&self, id: &str, claims: Option<&Claims>) -> Result<Arc<Session>> {
+        let session = self
+            .sessions
+            .get(id)
+            .map(|entry| entry.value().clone())
+            .ok_or_else(|| not_found(id))?;
+
+        let claims = claims
+            .ok_or_else(|| AppError::Unauthorized("使用交互式事务需要登录".to_string()))?;
+        if claims.sub != session.owner {
+            return Err(AppError::Forbidden("无权使用其他用户的事务".to_string()));
+        }
+
+        Ok(session)
+    }
+
+    /// 独占事务的连接，直到返回值被释放；事务正被其他请求使用时等待
+    async fn pin(&self, id: &str, claims: Option<&Claims>) -> Result<PinnedConnection> {
+        let session = self.session(id, claims)?;
+        let guard = session.tx.clone().lock_owned().await;
+        let tx = OwnedMutexGuard::try_map(guard, Option::as_mut).map_err(|_| not_found(id))?;
+        session.touch();
+        Ok(PinnedConnection { session, tx })
+    }
+
+    /// 结束事务：从注册表移除并取出事务（等待正在执行的请求结束），由调用方提交或回滚
+    ///
+    /// claims 为 None 时（空闲超时回滚）不检查事务所有者
+    async fn take(&self, id: &str, claims: Option<&Claims>) -> Result<PinnedTransaction> {
+        if claims.is_some() {
+            self.session(id, claims)?;
+        }
+        let (_, session) = self.sessions.remove(id).ok_or_else(|| not_found(id))?;
+        self.release(session.owner);
+
+        let tx = session.tx.lock().await.take();
+        tx.ok_or_else(|| not_found(id))
+    }
+
+    fn release(&self, owner: i32) {
+        if let Some(mut open) = self.open_per_user.get_mut(&owner) {
+            *open = open.saturating_sub(1);
+        }
+    }
+
+    /// 回滚空闲超时的事务（正在执行请求的事务不算空闲）
+    async fn rollback_idle(&self) {
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|entry| entry.idle_for() >= *IDLE_TIMEOUT && entry.tx.try_lock().is_ok())
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        for id in expired {
+            let Ok(tx) = self.take(&id, None).await else {
+                continue;
+            };
+            tracing::warn!(
+                "交互式事务 {} 空闲超过 {} 秒，已回滚",
+                id,
+                IDLE_TIMEOUT.as_secs()
+            );
+            if let Err(e) = tx.rollback().await {
+                tracing::warn!("回滚交互式事务 {} 失败: {}", id, e);
+            }
+        }
+    }
+}
+
+/// 占用的用户事务名额：事务注册成功前被释放时（开启失败，或客户端断开导致请求被取消）归还名额
+struct SlotGuard<'a> {
+    registry: &'a SessionRegistry,
+    owner: i32,
+    kept: bool,
+}
+
+impl SlotGuard<'_> {
+    /// 事务已注册，名额保留到事务结束
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for SlotGuard<'_> {
+    fn drop(&mut self) {
+        if !self.kept {
+            self.registry.release(self.owner);
+        }
+    }
+}
+
+fn not_found(id: &str) -> AppError {
+    AppError::NotFound(format!("交互式事务不存在或已结束: {}", id))
+}
+
+/// 启动后台任务，定期回滚空闲超时的交互式事务
+pub fn spawn_idle_reaper() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            SESSIONS.rollback_idle().await;
+        }
+    });
+}
+
+/// 交互式事务固定的连接，释放时记录最近使用时间
+pub struct PinnedConnection {
+    session: Arc<Session>,
+    tx: OwnedMappedMutexGuard<Option<PinnedTransaction>, PinnedTransaction>,
+}
+
+impl Drop for PinnedConnection {
+    fn drop(&mut self) {
+        self.session.touch();
+    }
+}
+
+/// 请求使用的数据库连接
+///
+/// 普通请求在第一次执行 SQL 时从连接池获取连接；带 X-Transaction-Id 的请求在交互式事务的连接上执行，
+/// 写操作的 begin 和 begin_read 会成为 SAVEPOINT，请求失败时只回滚该请求自己的语句
+pub enum DbConnection {
+    Pool {
+        pool: PgPool,
+        conn: Option<Box<PoolConnection<Postgres>>>,
+    },
+    Pinned(PinnedConnection),
+}
+
+impl DbConnection {
+    /// 获取执行 SQL 的连接
+    pub async fn acquire(&mut self) -> Result<&mut PgConnection> {
+        match self {
+            DbConnection::Pool { pool, conn } => {
+                if conn.is_none() {
+                    *conn = Some(Box::new(pool.acquire().await?));
+                }
+                conn.as_deref_mut()
+                    .map(|conn| &mut **conn)
+                    .ok_or_else(|| AppError::Internal("获取数据库连接失败".to_string()))
+            }
+            DbConnection::Pinned(pinned) => Ok(&mut **pinned.tx),
+        }
+    }
+
+    /// 获取读请求使用的连接
+    ///
+    /// 交互式事务中开启 SAVEPOINT：读失败（如类型转换错误、语句超时）时只回滚到该保存点，事务仍可继续使用
+    pub async fn begin_read(&mut self) -> Result<ReadConnection<'_>> {
+        let pinned = self.is_pinned();
+        let conn = self.acquire().await?;
+        Ok(if pinned {
+            ReadConnection::Savepoint(Box::new(conn.begin().await?))
+        } else {
+            ReadConnection::Direct(conn)
+        })
+    }
+
+    /// 是否在交互式事务中
+    pub fn is_pinned(&self) -> bool {
+        matches!(self, DbConnection::Pinned(_))
+    }
+}
+
+/// 读请求使用的连接，成功后调用 finish；提前释放时回滚到保存点
+pub enum ReadConnection<'c> {
+    Direct(&'c mut PgConnection),
+    Savepoint(Box<Transaction<'c, Postgres>>),
+}
+
+impl ReadConnection<'_> {
+    /// 读请求完成，释放保存点
+    pub async fn finish(self) -> Result<()> {
+        if let ReadConnection::Savepoint(savepoint) = self {
+            savepoint.commit().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for ReadConnection<'_> {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        match self {
+            ReadConnection::Direct(conn) => conn,
+            ReadConnection::Savepoint(savepoint) => savepoint,
+        }
+    }
+}
+
+impl DerefMut for ReadConnection<'_> {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        match self {
+            ReadConnection::Direct(conn) => conn,
+            ReadConnection::Savepoint(savepoint) => savepoint,
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<PgPool> for DbConnection {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, pool: &PgPool) -> Result<Self> {
+        let Some(id) = parts.headers.get(TRANSACTION_ID_HEADER) else {
+            return Ok(DbConnection::Pool {
+                pool: pool.clone(),
+                conn: None,
+            });
+        };
+        let id = id
+            .to_str()
+            .map_err(|_| AppError::InvalidQuery("无效的 X-Transaction-Id".to_string()))?;
+
+        let pinned = SESSIONS.pin(id, parts.extensions.get::<Claims>()).await?;
+        Ok(DbConnection::Pinned(pinned))
+    }
+}
+
+/// POST /transaction/begin - 开启交互式事务
+///
+/// 请求体可选，支持与 /transaction 相同的 isolation、read_only 和 deferrable
+pub async fn begin_transaction(
+    State(pool): State<PgPool>,
+    Extension(claims): Extension<Claims>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Value>)> {
+    let options: TransactionOptions = if body.iter().all(u8::is_ascii_whitespace) {
+        TransactionOptions::default()
+    } else {
+        serde_json::from_slice(&body)?
+    };
+
+    let id = SESSIONS.begin(&pool, claims.sub, &options).await?;
+    tracing::info!("用户 {} 开启交互式事务 {}", claims.sub, id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "transaction_id": id,
+            "idle_timeout_secs": IDLE_TIMEOUT.as_secs(),
+        })),
+    ))
+}
+
+/// POST /transaction/:id/commit - 提交交互式事务
+pub async fn commit_transaction(
+    Path(id): Path<String>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Value>> {
+    let mut tx = SESSIONS.take(&id, Some(&claims)).await?;
+
+    // 事务中有语句失败后 PostgreSQL 会把 COMMIT 当作 ROLLBACK 执行，需要先检查事务是否已中止
+    if let Err(e) = sqlx::query("SELECT 1").execute(&mut *tx).await {
+        tracing::warn!("交互式事务 {} 已中止，回滚: {}", id, e);
+        tx.rollback().await?;
+        return Err(AppError::InvalidQuery(
+            "事务中有语句执行失败，已回滚，没有提交任何修改".to_string(),
+        ));
+    }
+
+    tx.commit().await?;
+    tracing::info!("交互式事务 {} 已提交", id);
+
+    Ok(Json(json!({
+        "transaction_id": id,
+        "status": "committed",
+    })))
+}
+
+/// POST /transaction/:id/rollback - 回滚交互式事务
+pub async fn rollback_transaction(
+    Path(id): Path<String>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Value>> {
+    let tx = SESSIONS.take(&id, Some(&claims)).await?;
+    tx.rollback().await?;
+    tracing::info!("交互式事务 {} 已回滚", id);
+
+    Ok(Json(json!({
+        "transaction_id": id,
+        "status": "rolled_back",
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(user_id: i32) -> Claims {
+        Claims::new(user_id, format!("user{}@example.com", user_id), "user".to_string())
+    }
+
+    #[test]
+    fn test_session_owner() {
+        let registry = SessionRegistry::default();
+        registry.insert("t1".to_string(), 1, None);
+
+        assert!(registry.session("t1", Some(&claims(1))).is_ok());
+        assert!(matches!(registry.session("t1", Some(&claims(2))), Err(AppError::Forbidden(_))));
+        assert!(matches!(registry.session("t1", None), Err(AppError::Unauthorized(_))));
+        assert!(matches!(registry.session("t2", Some(&claims(1))), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_per_user_limit() {
+        let registry = SessionRegistry::default();
+        let slots: Vec<_> = (0..*MAX_PER_USER).map(|_| registry.reserve(1).unwrap()).collect();
+        assert!(matches!(registry.reserve(1), Err(AppError::TooManyRequests(_))));
+        assert!(registry.reserve(2).is_ok());
+
+        // 未注册事务的名额在释放时归还（如 begin 被取消）
+        drop(slots);
+        for _ in 0..*MAX_PER_USER {
+            registry.reserve(1).unwrap().keep();
+        }
+        assert!(registry.reserve(1).is_err());
+
+        registry.release(1);
+        assert!(registry.reserve(1).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_idle() {
+        let registry = SessionRegistry::default();
+        for (owner, id) in [(1, "idle"), (2, "busy"), (3, "active")] {
+            registry.reserve(owner).unwrap().keep();
+            registry.insert(id.to_string(), owner, None);
+        }
+
+        let long_ago = Instant::now() - *IDLE_TIMEOUT * 2;
+        for id in ["idle", "busy"] {
+            *registry.sessions.get(id).unwrap().last_used.lock().unwrap() = long_ago;
+        }
+
+        // 正在执行请求的事务不算空闲
+        let busy = registry.sessions.get("busy").unwrap().tx.clone();
+        let _in_use = busy.lock().await;
+
+        registry.rollback_idle().await;
+        assert!(!registry.sessions.contains_key("idle"));
+        assert!(registry.sessions.contains_key("busy"));
+        assert!(registry.sessions.contains_key("active"));
+        assert_eq!(registry.open_per_user.get(&1).map(|open| *open), Some(0));
+        assert_eq!(registry.open_per_user.get(&2).map(|open| *open), Some(1));
+    }
+}
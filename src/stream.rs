@@ -0,0 +1,436 @@
+//! 大结果集的流式响应
+//!
+//! 查询结果通过 sqlx 的 fetch 流逐行读取，编码后经有界通道写入响应体：
+//! 客户端读取变慢时通道写满，读取任务随之暂停，不再从连接上取数据（背压）；
+//! 客户端断开时取消仍在执行的查询并关闭该连接。
+//! 由数据库生成 JSON 的普通列表响应（stream_page）也经同一通道写出。
+
+use crate::error::{AppError, Result};
+use crate::query_builder::QueryParams;
+use crate::row_encoder::row_to_json;
+use crate::session::DbConnection;
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use once_cell::sync::Lazy;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgArguments, PgConnection, PgRow};
+use sqlx::{PgPool, Postgres, Row};
+use std::collections::HashMap;
+use tokio::sync::{mpsc, oneshot};
+
+/// NDJSON 媒体类型：Accept 中包含时逐行输出 JSON 对象
+pub const NDJSON_MEDIA_TYPE: &str = "application/x-ndjson";
+
+/// 每次写入响应体的数据块大小
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// 读取任务与响应体之间最多缓冲的数据块数
+const BUFFERED_CHUNKS: usize = 8;
+
+/// 流式响应的行数上限（STREAM_MAX_ROWS 为默认值，STREAM_ROUTE_MAX_ROWS 按路由覆盖）
+pub static STREAM_ROW_CAPS: Lazy<RowCaps> = Lazy::new(|| {
+    RowCaps::parse(
+        std::env::var("STREAM_MAX_ROWS").ok().as_deref(),
+        std::env::var("STREAM_ROUTE_MAX_ROWS").ok().as_deref(),
+    )
+    .unwrap_or_else(|e| {
+        tracing::warn!("流式响应行数上限配置无效，已忽略: {}", e);
+        RowCaps::default()
+    })
+});
+
+/// 流式响应的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// 每行一个 JSON 对象（Accept: application/x-ndjson）
+    Ndjson,
+    /// 分块输出的 JSON 数组（Prefer: stream）
+    JsonArray,
+}
+
+impl StreamFormat {
+    /// 根据 Accept 与 Prefer: stream 判断是否以流式返回
+    pub fn from_request(headers: &HeaderMap, prefer_stream: bool) -> Option<Self> {
+        let ndjson = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|media| media.split(';').next().unwrap_or("").trim() == NDJSON_MEDIA_TYPE);
+
+        if ndjson {
+            Some(StreamFormat::Ndjson)
+        } else if prefer_stream {
+            Some(StreamFormat::JsonArray)
+        } else {
+            None
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            StreamFormat::Ndjson => NDJSON_MEDIA_TYPE,
+            StreamFormat::JsonArray => "application/json",
+        }
+    }
+
+    fn open(self) -> &'static [u8] {
+        match self {
+            StreamFormat::Ndjson => b"",
+            StreamFormat::JsonArray => b"[",
+        }
+    }
+
+    fn close(self) -> &'static [u8] {
+        match self {
+            StreamFormat::Ndjson => b"",
+            StreamFormat::JsonArray => b"]",
+        }
+    }
+}
+
+/// 流式响应的行数上限配置
+#[derive(Debug, Clone, Default)]
+pub struct RowCaps {
+    default: Option<i64>,
+    routes: HashMap<String, i64>,
+}
+
+impl RowCaps {
+    /// 解析默认上限和按路由的上限（格式：schema.table=N,schema.table=N）
+    pub fn parse(default: Option<&str>, routes: Option<&str>) -> Result<Self> {
+        let parse_cap = |value: &str| {
+            value
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|cap| *cap > 0)
+                .ok_or_else(|| AppError::InvalidQuery(format!("无效的行数上限: {}", value)))
+        };
+
+        let default = default
+            .filter(|value| !value.trim().is_empty())
+            .map(parse_cap)
+            .transpose()?;
+
+        let mut caps = HashMap::new();
+        for entry in routes.unwrap_or("").split(',').filter(|e| !e.trim().is_empty()) {
+            let (route, cap) = entry
+                .split_once('=')
+                .ok_or_else(|| AppError::InvalidQuery(format!("无效的路由上限: {}", entry)))?;
+            caps.insert(route.trim().to_string(), parse_cap(cap)?);
+        }
+
+        Ok(RowCaps {
+            default,
+            routes: caps,
+        })
+    }
+
+    /// 某个表路由的行数上限
+    pub fn for_route(&self, schema: &str, table: &str) -> Option<i64> {
+        self.routes
+            .get(&format!("{}.{}", schema, table))
+            .copied()
+            .or(self.default)
+    }
+}
+
+/// 对流式查询应用行数上限：显式 limit 超过上限时拒绝；未指定时以上限作为 limit，
+/// 并返回需要在输出前确认结果没有超出的上限（见 SqlBuilder::build_exceeds）
+pub fn apply_row_cap(params: &mut QueryParams, cap: Option<i64>) -> Result<Option<i64>> {
+    let Some(cap) = cap else {
+        return Ok(None);
+    };
+    match params.limit {
+        Some(limit) if limit > cap => Err(AppError::InvalidQuery(format!(
+            "limit={} 超过流式响应的上限 {} 行",
+            limit, cap
+        ))),
+        Some(_) => Ok(None),
+        None => {
+            params.limit = Some(cap);
+            Ok(Some(cap))
+        }
+    }
+}
+
+/// 以流式响应返回查询结果
+///
+/// in_database 为 true 时 SQL 的唯一一列是 PostgreSQL 生成的 JSON 文本，否则逐行编码。
+/// 行数上限由调用方在 SQL 中以 limit 限定，并在调用前确认结果没有超出。
+pub async fn stream_rows(
+    pool: PgPool,
+    sql: String,
+    args: PgArguments,
+    format: StreamFormat,
+    in_database: bool,
+) -> Result<Response> {
+    let (tx, rx) = mpsc::channel(BUFFERED_CHUNKS);
+
+    tokio::spawn(async move {
+        let mut conn = match pool.acquire().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                let _ = tx.send(Err(e.into())).await;
+                return;
+            }
+        };
+        let pid = match backend_pid(&mut conn).await {
+            Ok(pid) => pid,
+            Err(e) => {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+
+        if pump(&mut conn, &sql, args, format, in_database, None, &tx).await == Pumped::Abandoned {
+            abandon(&pool, pid, conn).await;
+        }
+    });
+
+    let body = channel_body(rx).await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    Ok((StatusCode::OK, headers, body).into_response())
+}
+
+/// 在请求自己的连接上执行 SqlBuilder::build_select_json_page 生成的查询，
+/// 把 PostgreSQL 生成的 JSON 文本逐块写入 JSON 数组响应体，返回 (本页行数, 响应体)
+///
+/// 本页行数来自 SQL 第二列的窗口函数，第一行到达时即已确定，调用方据此设置 Content-Range；
+/// 服务端只缓冲当前数据块。交互式事务中在保存点内读取，失败或客户端断开时只回滚到该保存点
+pub async fn stream_page(
+    mut db: DbConnection,
+    sql: String,
+    args: PgArguments,
+) -> Result<(usize, Body)> {
+    let (tx, rx) = mpsc::channel(BUFFERED_CHUNKS);
+    let (rows_tx, rows_rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let pid = match pooled_backend_pid(&mut db).await {
+            Ok(pid) => pid,
+            Err(e) => {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+        let mut conn = match db.begin_read().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+
+        let format = StreamFormat::JsonArray;
+        match pump(&mut conn, &sql, args, format, true, Some(rows_tx), &tx).await {
+            Pumped::Finished => {
+                if let Err(e) = conn.finish().await {
+                    let _ = tx.send(Err(e)).await;
+                }
+            }
+            // 错误已写入通道，保存点随 conn 释放回滚
+            Pumped::Failed => {}
+            Pumped::Abandoned => {
+                drop(conn);
+                // 交互式事务的连接不能关闭，未读完的结果由事务的下一条语句丢弃
+                if let (Some(pid), DbConnection::Pool { pool, conn: Some(conn) }) = (pid, db) {
+                    abandon(&pool, pid, *conn).await;
+                }
+            }
+        }
+    });
+
+    let body = channel_body(rx).await?;
+    let rows = rows_rx
+        .await
+        .map_err(|_| AppError::Internal("流式查询意外结束".to_string()))?;
+    Ok((rows as usize, body))
+}
+
+/// 等待第一块数据后以通道为数据源构造响应体：查询本身出错时仍可返回普通的错误响应
+async fn channel_body(mut rx: mpsc::Receiver<Result<Bytes>>) -> Result<Body> {
+    let first = match rx.recv().await {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) => return Err(e),
+        None => return Err(AppError::Internal("流式查询意外结束".to_string())),
+    };
+    let rest = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Ok(Body::from_stream(stream::once(async move { Ok(first) }).chain(rest)))
+}
+
+async fn backend_pid(conn: &mut PgConnection) -> Result<i32> {
+    Ok(sqlx::query_scalar("SELECT pg_backend_pid()")
+        .fetch_one(conn)
+        .await?)
+}
+
+/// 普通请求所用连接的后端 pid，交互式事务的连接返回 None
+async fn pooled_backend_pid(db: &mut DbConnection) -> Result<Option<i32>> {
+    if db.is_pinned() {
+        return Ok(None);
+    }
+    Ok(Some(backend_pid(db.acquire().await?).await?))
+}
+
+/// 放弃的查询可能仍在执行：先取消，再关闭连接，避免取消信号落到复用该连接的后续查询上
+async fn abandon(pool: &PgPool, pid: i32, conn: PoolConnection<Postgres>) {
+    if let Err(e) = sqlx::query("SELECT pg_cancel_backend($1)")
+        .bind(pid)
+        .execute(pool)
+        .await
+    {
+        tracing::warn!("取消流式查询失败 (pid={}): {}", pid, e);
+    }
+    let _ = conn.close().await;
+}
+
+/// pump 的结束方式
+#[derive(Debug, PartialEq, Eq)]
+enum Pumped {
+    /// 结果已全部写入通道
+    Finished,
+    /// 查询在数据库端失败，错误已写入通道，连接可以继续使用
+    Failed,
+    /// 客户端断开或编码失败，查询可能仍在执行
+    Abandoned,
+}
+
+/// 逐行读取结果并写入通道
+///
+/// page_rows 不为 None 时从第一行的第二列读取本页行数并发送（没有结果时发送 0）
+async fn pump(
+    conn: &mut PgConnection,
+    sql: &str,
+    args: PgArguments,
+    format: StreamFormat,
+    in_database: bool,
+    mut page_rows: Option<oneshot::Sender<i64>>,
+    tx: &mpsc::Sender<Result<Bytes>>,
+) -> Pumped {
+    let mut rows = sqlx::query_with(sql, args).fetch(conn);
+    let mut buf = Vec::with_capacity(CHUNK_BYTES);
+    buf.extend_from_slice(format.open());
+    let mut count: i64 = 0;
+
+    loop {
+        let next = tokio::select! {
+            next = rows.try_next() => next,
+            _ = tx.closed() => {
+                tracing::debug!("客户端已断开，放弃流式查询（已输出 {} 行）", count);
+                return Pumped::Abandoned;
+            }
+        };
+
+        let row = match next {
+            Ok(Some(row)) => row,
+            Ok(None) => break,
+            Err(e) => {
+                let _ = tx.send(Err(e.into())).await;
+                return Pumped::Failed;
+            }
+        };
+
+        if let Some(sender) = page_rows.take() {
+            match row.try_get(1) {
+                Ok(rows) => {
+                    let _ = sender.send(rows);
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e.into())).await;
+                    return Pumped::Abandoned;
+                }
+            }
+        }
+
+        count += 1;
+        if count > 1 && format == StreamFormat::JsonArray {
+            buf.push(b',');
+        }
+        if let Err(e) = encode_row(&row, in_database, &mut buf) {
+            let _ = tx.send(Err(e)).await;
+            return Pumped::Abandoned;
+        }
+        if format == StreamFormat::Ndjson {
+            buf.push(b'\n');
+        }
+
+        if buf.len() >= CHUNK_BYTES
+            && tx
+                .send(Ok(Bytes::from(std::mem::take(&mut buf))))
+                .await
+                .is_err()
+        {
+            tracing::debug!("客户端已断开，放弃流式查询（已输出 {} 行）", count);
+            return Pumped::Abandoned;
+        }
+    }
+
+    if let Some(sender) = page_rows {
+        let _ = sender.send(0);
+    }
+    buf.extend_from_slice(format.close());
+    let _ = tx.send(Ok(Bytes::from(buf))).await;
+    Pumped::Finished
+}
+
+fn encode_row(row: &PgRow, in_database: bool, buf: &mut Vec<u8>) -> Result<()> {
+    if in_database {
+        let text: &str = row.try_get(0)?;
+        buf.extend_from_slice(text.as_bytes());
+    } else {
+        serde_json::to_writer(&mut *buf, &row_to_json(row))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_format() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json;q=0.5, application/x-ndjson"),
+        );
+        assert_eq!(StreamFormat::from_request(&headers, true), Some(StreamFormat::Ndjson));
+        assert_eq!(
+            StreamFormat::from_request(&HeaderMap::new(), true),
+            Some(StreamFormat::JsonArray)
+        );
+        assert_eq!(StreamFormat::from_request(&HeaderMap::new(), false), None);
+    }
+
+    #[test]
+    fn test_row_caps() {
+        let caps = RowCaps::parse(Some("1000"), Some("public.events=50000, audit.logs=10")).unwrap();
+        assert_eq!(caps.for_route("public", "events"), Some(50000));
+        assert_eq!(caps.for_route("audit", "logs"), Some(10));
+        assert_eq!(caps.for_route("public", "orders"), Some(1000));
+        assert_eq!(RowCaps::parse(None, None).unwrap().for_route("public", "orders"), None);
+
+        assert!(RowCaps::parse(Some("0"), None).is_err());
+        assert!(RowCaps::parse(None, Some("public.events")).is_err());
+
+        let mut params = QueryParams::default();
+        assert_eq!(apply_row_cap(&mut params, Some(100)).unwrap(), Some(100));
+        assert_eq!(params.limit, Some(100));
+        // 显式 limit 不超过上限时结果不会超出，无需检查
+        assert_eq!(apply_row_cap(&mut params, Some(100)).unwrap(), None);
+        params.limit = Some(500);
+        assert!(apply_row_cap(&mut params, Some(100)).is_err());
+    }
+}
@@ -1,35 +1,138 @@
 use axum::{extract::State, http::StatusCode, Json};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use sqlx::{Column, PgPool, Postgres, Row, Transaction};
+use serde_json::{Map, Value};
+use sqlx::postgres::PgArguments;
+use sqlx::{Connection, PgPool, Postgres, Transaction};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::error::AppError;
-use crate::query_builder::QueryParams;
+use crate::prefer::Resolution;
+use crate::query_builder::{QueryParams, SqlBuilder};
+use crate::row_encoder::rows_to_json;
+use crate::schema_cache::{DatabaseSchema, MAIN_DATABASE_ID, SCHEMA_CACHE};
+use crate::schema_handlers::{prepare_upsert, resolve_embeds};
 
 /// 事务操作类型
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum OperationType {
+    Get,    // 查询
     Post,   // 插入
     Patch,  // 更新
     Delete, // 删除
+    Upsert, // 插入或更新（ON CONFLICT）
 }
 
 /// 单个事务操作
-#[derive(Debug, Deserialize)]
+///
+/// data 和 where 中的 {"$ref": "操作.列"} 会替换为之前操作 RETURNING 结果中的值，
+/// 操作可以用序号（从 0 开始）或 name 指定，如 "0.id"、"new_order.id"、"new_order.1.id"（第 2 行）
+#[derive(Debug, Deserialize, Clone)]
 pub struct TransactionOperation {
+    /// 操作名称（可选），供后续操作引用其结果
+    pub name: Option<String>,
     /// 操作类型
     pub method: OperationType,
     /// Schema 名称
     pub schema: String,
     /// 表名
     pub table: String,
-    /// WHERE 条件（用于 PATCH 和 DELETE）
+    /// WHERE 条件（用于 GET、PATCH 和 DELETE），语法与 REST 查询参数相同：
+    /// {"id": 1, "age.gte": 18, "status.in": "active,pending", "or": "(a.eq.1,b.gt.2)"}
     #[serde(rename = "where")]
-    pub conditions: Option<HashMap<String, String>>,
-    /// 数据（用于 POST 和 PATCH）
+    pub conditions: Option<HashMap<String, Value>>,
+    /// 数据（用于 POST、PATCH 和 UPSERT）
     pub data: Option<Value>,
+    /// 返回的列（用于 GET），语法与 select 查询参数相同
+    pub select: Option<String>,
+    /// 排序（用于 GET），如 "created_at.desc,id"
+    pub order: Option<String>,
+    /// 最多返回的行数（用于 GET）
+    pub limit: Option<i64>,
+    /// 冲突列（用于 UPSERT，默认为主键）
+    pub on_conflict: Option<Vec<String>>,
+    /// 冲突处理方式（用于 UPSERT，默认 merge-duplicates）
+    pub resolution: Option<Resolution>,
+}
+
+/// 操作失败时的处理方式
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// 任一操作失败即回滚整个事务（默认）
+    #[default]
+    RollbackAll,
+    /// 每个操作在独立的保存点中执行，失败的操作单独回滚，其余操作照常提交
+    Skip,
+}
+
+/// 事务隔离级别
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn as_sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// 事务模式（隔离级别、只读、可延迟）
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub struct TransactionOptions {
+    /// 隔离级别，默认使用数据库的设置（通常为 READ COMMITTED）
+    pub isolation: Option<IsolationLevel>,
+    /// 只读事务
+    #[serde(default)]
+    pub read_only: bool,
+    /// 可延迟事务（仅对 SERIALIZABLE 只读事务生效：等待安全快照，之后不会因序列化冲突失败）
+    #[serde(default)]
+    pub deferrable: bool,
+}
+
+impl TransactionOptions {
+    /// 设置事务模式的 SET TRANSACTION 语句，全部为默认值时返回 None
+    fn set_transaction_sql(&self) -> Option<String> {
+        let mut modes = Vec::new();
+        if let Some(isolation) = self.isolation {
+            modes.push(format!("ISOLATION LEVEL {}", isolation.as_sql()));
+        }
+        if self.read_only {
+            modes.push("READ ONLY".to_string());
+        }
+        if self.deferrable {
+            modes.push("DEFERRABLE".to_string());
+        }
+        (!modes.is_empty()).then(|| format!("SET TRANSACTION {}", modes.join(", ")))
+    }
+
+    /// 开启事务并设置事务模式
+    pub async fn begin(&self, pool: &PgPool) -> Result<Transaction<'static, Postgres>, AppError> {
+        let mut tx = pool.begin().await?;
+        if let Some(sql) = self.set_transaction_sql() {
+            sqlx::query(&sql).execute(&mut *tx).await?;
+        }
+        Ok(tx)
+    }
+}
+
+/// 序列化失败或死锁时的默认重试次数
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// 允许的最大重试次数
+const MAX_RETRIES: u32 = 10;
+
+fn default_max_retries() -> u32 {
+    DEFAULT_MAX_RETRIES
 }
 
 /// 事务请求
@@ -37,6 +140,50 @@ pub struct TransactionOperation {
 pub struct TransactionRequest {
     /// 操作列表
     pub operations: Vec<TransactionOperation>,
+    /// 操作失败时的处理方式
+    #[serde(default)]
+    pub on_error: OnError,
+    /// 事务模式
+    #[serde(flatten)]
+    pub options: TransactionOptions,
+    /// 序列化失败（40001）或死锁（40P01）时整个事务的最大重试次数
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+/// 单个操作的执行状态
+#[derive(Debug, Serialize)]
+pub struct OperationStatus {
+    /// 操作序号
+    pub index: usize,
+    /// "ok" 或 "error"
+    pub status: &'static str,
+    /// 错误信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 数据库错误的 SQLSTATE
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sqlstate: Option<String>,
+}
+
+impl OperationStatus {
+    fn ok(index: usize) -> Self {
+        OperationStatus {
+            index,
+            status: "ok",
+            error: None,
+            sqlstate: None,
+        }
+    }
+
+    fn failed(index: usize, error: &AppError) -> Self {
+        OperationStatus {
+            index,
+            status: "error",
+            error: Some(error.to_string()),
+            sqlstate: error.sqlstate(),
+        }
+    }
 }
 
 /// 事务响应
@@ -44,8 +191,14 @@ pub struct TransactionRequest {
 pub struct TransactionResponse {
     /// 成功的操作数
     pub success_count: usize,
-    /// 每个操作的结果
+    /// 失败并被跳过的操作数（仅 on_error=skip 时可能非 0）
+    pub failed_count: usize,
+    /// 每个操作的结果，失败的操作为 null
     pub results: Vec<Value>,
+    /// 每个操作的执行状态
+    pub operations: Vec<OperationStatus>,
+    /// 执行次数（包括因序列化失败或死锁的重试）
+    pub attempts: u32,
     /// 总耗时（毫秒）
     pub elapsed_ms: u128,
 }
@@ -71,10 +224,68 @@ pub async fn execute_transaction(
         )));
     }
 
-    // 开启事务
-    let mut tx = pool.begin().await?;
+    if req.max_retries > MAX_RETRIES {
+        return Err(AppError::InvalidQuery(format!(
+            "max_retries 不能超过 {}",
+            MAX_RETRIES
+        )));
+    }
+
+    let names = operation_names(&req.operations)?;
+
+    let mut attempts = 1;
+    let (results, statuses) = loop {
+        match run_transaction(&pool, &req, &names).await {
+            Ok(outcome) => break outcome,
+            Err(e) if attempts <= req.max_retries && is_retryable(&e) => {
+                let delay = retry_delay(attempts);
+                tracing::warn!(
+                    "事务第 {} 次执行失败，{}ms 后重试: {}",
+                    attempts,
+                    delay.as_millis(),
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                attempts += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    let elapsed = start.elapsed().as_millis();
+    let failed_count = statuses.iter().filter(|s| s.status == "error").count();
+
+    tracing::info!(
+        "事务执行成功: {} 个操作（{} 个失败），执行 {} 次，耗时 {}ms",
+        results.len(),
+        failed_count,
+        attempts,
+        elapsed
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(TransactionResponse {
+            success_count: results.len() - failed_count,
+            failed_count,
+            results,
+            operations: statuses,
+            attempts,
+            elapsed_ms: elapsed,
+        }),
+    ))
+}
+
+/// 执行一次完整的事务：开启、依次执行操作、提交
+async fn run_transaction(
+    pool: &PgPool,
+    req: &TransactionRequest,
+    names: &HashMap<String, usize>,
+) -> Result<(Vec<Value>, Vec<OperationStatus>), AppError> {
+    let mut tx = req.options.begin(pool).await?;
 
     let mut results = Vec::new();
+    let mut statuses = Vec::new();
 
     // 执行每个操作
     for (index, op) in req.operations.iter().enumerate() {
@@ -87,282 +298,401 @@ pub async fn execute_transaction(
             op.table
         );
 
-        let result = match op.method {
-            OperationType::Post => execute_insert(&mut tx, op).await?,
-            OperationType::Patch => execute_update(&mut tx, op).await?,
-            OperationType::Delete => execute_delete(&mut tx, op).await?,
+        let references = References {
+            results: &results,
+            names,
+        };
+        let outcome = match req.on_error {
+            OnError::RollbackAll => execute_operation(pool, &mut tx, op, &references).await,
+            OnError::Skip => {
+                // 嵌套事务即 SAVEPOINT：成功时 RELEASE，失败时 ROLLBACK TO
+                let mut savepoint = tx.begin().await?;
+                let outcome = execute_operation(pool, &mut savepoint, op, &references).await;
+                if outcome.is_ok() {
+                    savepoint.commit().await?;
+                } else {
+                    savepoint.rollback().await?;
+                }
+                outcome
+            }
         };
 
-        results.push(result);
+        match outcome {
+            Ok(result) => {
+                results.push(result);
+                statuses.push(OperationStatus::ok(index));
+            }
+            // 序列化失败和死锁需要重试整个事务，不能只跳过当前操作
+            Err(e) if req.on_error == OnError::Skip && !is_retryable(&e) => {
+                tracing::warn!("事务操作 {} 失败，已跳过: {}", index, e);
+                results.push(Value::Null);
+                statuses.push(OperationStatus::failed(index, &e));
+            }
+            Err(e) => {
+                return Err(AppError::Operation {
+                    index,
+                    source: Box::new(e),
+                })
+            }
+        }
     }
 
-    // 提交事务
+    // 提交事务（SERIALIZABLE 下提交时也可能发生序列化失败）
     tx.commit().await.map_err(|e| {
         tracing::error!("事务提交失败: {}", e);
         AppError::Database(e)
     })?;
 
-    let elapsed = start.elapsed().as_millis();
+    Ok((results, statuses))
+}
 
-    tracing::info!(
-        "事务执行成功: {} 个操作，耗时 {}ms",
-        results.len(),
-        elapsed
-    );
+/// 是否为可以通过重试整个事务解决的错误：序列化失败（40001）或死锁（40P01）
+fn is_retryable(error: &AppError) -> bool {
+    matches!(error.sqlstate().as_deref(), Some("40001" | "40P01"))
+}
 
-    Ok((
-        StatusCode::OK,
-        Json(TransactionResponse {
-            success_count: results.len(),
-            results,
-            elapsed_ms: elapsed,
-        }),
-    ))
+/// 第 attempt 次失败后的等待时间：指数退避（20ms 起，最长 1s），加上随机抖动避免冲突的事务同时重试
+fn retry_delay(attempt: u32) -> std::time::Duration {
+    let base = (20u64 << attempt.saturating_sub(1).min(6)).min(1000);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    std::time::Duration::from_millis(base + nanos % (base / 2 + 1))
 }
 
-/// 执行插入操作
-async fn execute_insert(
+/// 替换 $ref 后执行单个操作
+async fn execute_operation(
+    pool: &PgPool,
     tx: &mut Transaction<'_, Postgres>,
     op: &TransactionOperation,
+    references: &References<'_>,
 ) -> Result<Value, AppError> {
-    let data = op
-        .data
-        .as_ref()
-        .ok_or_else(|| AppError::InvalidQuery("POST 操作需要提供 data 字段".to_string()))?;
+    let op = references
+        .resolve_operation(op)
+        .map_err(AppError::InvalidQuery)?;
+
+    match op.method {
+        OperationType::Get => execute_select(pool, tx, &op).await,
+        OperationType::Post => execute_insert(pool, tx, &op).await,
+        OperationType::Patch => execute_update(pool, tx, &op).await,
+        OperationType::Delete => execute_delete(pool, tx, &op).await,
+        OperationType::Upsert => execute_upsert(pool, tx, &op).await,
+    }
+}
 
-    // 验证标识符
-    QueryParams::sanitize_identifier(&op.schema)?;
-    QueryParams::sanitize_identifier(&op.table)?;
+/// 收集操作名称：名称不能重复，也不能是纯数字（与序号引用冲突）
+fn operation_names(operations: &[TransactionOperation]) -> Result<HashMap<String, usize>, AppError> {
+    let mut names = HashMap::new();
+    for (index, op) in operations.iter().enumerate() {
+        let Some(name) = &op.name else { continue };
+        if name.is_empty() || name.contains('.') || name.chars().all(|c| c.is_ascii_digit()) {
+            return Err(AppError::InvalidQuery(format!(
+                "操作 {}: 名称 \"{}\" 无效，不能为空、纯数字或包含 .",
+                index, name
+            )));
+        }
+        if names.insert(name.clone(), index).is_some() {
+            return Err(AppError::InvalidQuery(format!(
+                "操作 {}: 名称 \"{}\" 重复",
+                index, name
+            )));
+        }
+    }
+    Ok(names)
+}
+
+/// 之前操作的 RETURNING 结果，用于解析 $ref
+struct References<'a> {
+    results: &'a [Value],
+    names: &'a HashMap<String, usize>,
+}
 
-    // 构建 INSERT SQL
-    let obj = data
-        .as_object()
-        .ok_or_else(|| AppError::InvalidQuery("data 必须是 JSON 对象".to_string()))?;
+impl References<'_> {
+    /// 返回替换了 data 和 where 中全部引用的操作
+    fn resolve_operation(&self, op: &TransactionOperation) -> Result<TransactionOperation, String> {
+        let mut op = op.clone();
+        if let Some(data) = &op.data {
+            op.data = Some(self.resolve(data)?);
+        }
+        if let Some(conditions) = &op.conditions {
+            op.conditions = Some(
+                conditions
+                    .iter()
+                    .map(|(column, value)| Ok((column.clone(), self.resolve_value(value)?)))
+                    .collect::<Result<_, String>>()?,
+            );
+        }
+        Ok(op)
+    }
 
-    if obj.is_empty() {
-        return Err(AppError::InvalidQuery("data 不能为空".to_string()));
+    /// 替换 data 中的引用：data 为单个对象或对象数组，只替换每个对象的顶层列值
+    fn resolve(&self, data: &Value) -> Result<Value, String> {
+        match data {
+            Value::Object(row) => Ok(Value::Object(self.resolve_row(row)?)),
+            Value::Array(rows) => Ok(Value::Array(
+                rows.iter()
+                    .map(|row| match row {
+                        Value::Object(row) => Ok(Value::Object(self.resolve_row(row)?)),
+                        other => Ok(other.clone()),
+                    })
+                    .collect::<Result<_, String>>()?,
+            )),
+            other => Ok(other.clone()),
+        }
     }
 
-    let mut columns = Vec::new();
-    let mut placeholders = Vec::new();
+    fn resolve_row(&self, row: &Map<String, Value>) -> Result<Map<String, Value>, String> {
+        row.iter()
+            .map(|(column, value)| Ok((column.clone(), self.resolve_value(value)?)))
+            .collect()
+    }
 
-    for (i, key) in obj.keys().enumerate() {
-        QueryParams::sanitize_identifier(key)?;
-        columns.push(format!("\"{}\"", key));
-        placeholders.push(format!("${}", i + 1));
+    /// 列值为 {"$ref": "..."} 时替换为引用的值；JSON 列内部的同形对象按原样写入
+    fn resolve_value(&self, value: &Value) -> Result<Value, String> {
+        match value {
+            Value::Object(obj) if obj.len() == 1 && obj.contains_key("$ref") => match &obj["$ref"] {
+                Value::String(reference) => self.lookup(reference),
+                other => Err(format!("$ref 必须是字符串，收到 {}", other)),
+            },
+            other => Ok(other.clone()),
+        }
     }
 
-    let sql = format!(
-        "INSERT INTO \"{}\".\"{}\" ({}) VALUES ({}) RETURNING *",
-        op.schema,
-        op.table,
-        columns.join(", "),
-        placeholders.join(", ")
-    );
+    /// 解析 "操作.列" 或 "操作.行号.列"
+    fn lookup(&self, reference: &str) -> Result<Value, String> {
+        let invalid = |reason: String| format!("引用 \"{}\" 无效：{}", reference, reason);
+
+        let parts: Vec<&str> = reference.split('.').collect();
+        let (target, row, column) = match parts.as_slice() {
+            [target, column] => (*target, None, *column),
+            [target, row, column] => {
+                let row = row
+                    .parse::<usize>()
+                    .map_err(|_| invalid(format!("行号 {} 不是非负整数", row)))?;
+                (*target, Some(row), *column)
+            }
+            _ => return Err(invalid("格式应为 操作.列 或 操作.行号.列".to_string())),
+        };
+
+        let index = match target.parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => *self
+                .names
+                .get(target)
+                .ok_or_else(|| invalid(format!("没有名为 {} 的操作", target)))?,
+        };
+        let rows = match self.results.get(index) {
+            Some(Value::Array(rows)) => rows,
+            Some(_) => return Err(invalid(format!("操作 {} 执行失败，没有结果", index))),
+            None => {
+                return Err(invalid(format!(
+                    "只能引用之前已执行的操作，操作 {} 尚未执行",
+                    index
+                )))
+            }
+        };
 
-    tracing::debug!("执行 SQL: {}", sql);
+        let row = match row {
+            Some(row) => rows.get(row).ok_or_else(|| {
+                invalid(format!("操作 {} 只返回了 {} 行", index, rows.len()))
+            })?,
+            None => match rows.as_slice() {
+                [row] => row,
+                _ => {
+                    return Err(invalid(format!(
+                        "操作 {} 返回了 {} 行，请用 操作.行号.列 指定",
+                        index,
+                        rows.len()
+                    )))
+                }
+            },
+        };
 
-    // 构建查询
-    let mut query = sqlx::query(&sql);
-    for value in obj.values() {
-        query = bind_json_value(query, value);
+        row.get(column)
+            .cloned()
+            .ok_or_else(|| invalid(format!("操作 {} 的结果中没有列 {}", index, column)))
     }
-
-    // 执行并返回结果
-    let rows = query.fetch_all(&mut **tx).await?;
-    rows_to_json(rows)
 }
 
-/// 执行更新操作
-async fn execute_update(
-    tx: &mut Transaction<'_, Postgres>,
+/// 加载操作所在表的列类型，并按 REST 查询参数的语法解析 where（GET 还包括 select、order 和 limit）
+async fn operation_params(
+    pool: &PgPool,
     op: &TransactionOperation,
-) -> Result<Value, AppError> {
-    let data = op
-        .data
-        .as_ref()
-        .ok_or_else(|| AppError::InvalidQuery("PATCH 操作需要提供 data 字段".to_string()))?;
-
-    let conditions = op.conditions.as_ref().ok_or_else(|| {
-        AppError::InvalidQuery("PATCH 操作需要提供 where 条件".to_string())
-    })?;
-
+) -> Result<(Arc<DatabaseSchema>, QueryParams), AppError> {
     // 验证标识符
     QueryParams::sanitize_identifier(&op.schema)?;
     QueryParams::sanitize_identifier(&op.table)?;
 
-    // 构建 UPDATE SQL
-    let obj = data
-        .as_object()
-        .ok_or_else(|| AppError::InvalidQuery("data 必须是 JSON 对象".to_string()))?;
-
-    if obj.is_empty() {
-        return Err(AppError::InvalidQuery("data 不能为空".to_string()));
+    let is_get = matches!(op.method, OperationType::Get);
+    if !is_get && (op.select.is_some() || op.order.is_some() || op.limit.is_some()) {
+        return Err(AppError::InvalidQuery(
+            "select、order 和 limit 只能用于 GET 操作".to_string(),
+        ));
     }
 
-    let mut set_clauses = Vec::new();
-    let mut param_index = 1;
-
-    for key in obj.keys() {
-        QueryParams::sanitize_identifier(key)?;
-        set_clauses.push(format!("\"{}\" = ${}", key, param_index));
-        param_index += 1;
+    let mut query = HashMap::new();
+    for (key, value) in op.conditions.iter().flatten() {
+        if matches!(
+            key.as_str(),
+            "select" | "order" | "limit" | "offset" | "cursor" | "having" | "columns" | "on_conflict"
+        ) {
+            return Err(AppError::InvalidQuery(format!(
+                "where 中不能使用 {}，请使用操作的 select、order 和 limit 字段",
+                key
+            )));
+        }
+        let value = match value {
+            Value::String(s) => s.clone(),
+            Value::Number(_) | Value::Bool(_) => value.to_string(),
+            _ => {
+                return Err(AppError::InvalidQuery(format!(
+                    "where 条件 {} 的值必须是字符串、数字或布尔值（判断 NULL 请用 \"{}.is\": \"null\"）",
+                    key, key
+                )))
+            }
+        };
+        query.insert(key.clone(), value);
     }
 
-    let mut sql = format!(
-        "UPDATE \"{}\".\"{}\" SET {}",
-        op.schema,
-        op.table,
-        set_clauses.join(", ")
-    );
-
-    // 添加 WHERE 条件
-    let mut where_clauses = Vec::new();
-    for (key, _) in conditions.iter() {
-        QueryParams::sanitize_identifier(key)?;
-        where_clauses.push(format!("\"{}\" = ${}", key, param_index));
-        param_index += 1;
+    // 与 REST 写操作相同：无法识别的条件直接报错，而不是被忽略
+    let mut params = QueryParams::from_mutation_query(query.clone())?;
+    if is_get {
+        if let Some(select) = &op.select {
+            query.insert("select".to_string(), select.clone());
+        }
+        if let Some(order) = &op.order {
+            query.insert("order".to_string(), order.clone());
+        }
+        if let Some(limit) = op.limit {
+            query.insert("limit".to_string(), limit.to_string());
+        }
+        params = QueryParams::from_query_map(query)?;
     }
 
-    if !where_clauses.is_empty() {
-        sql.push_str(&format!(" WHERE {}", where_clauses.join(" AND ")));
+    let catalog = SCHEMA_CACHE
+        .for_table(pool, MAIN_DATABASE_ID, &op.schema, &op.table)
+        .await?;
+    params.column_types = catalog.column_types(&op.schema, &op.table).unwrap_or_default();
+    if is_get {
+        resolve_embeds(&catalog, &op.schema, &op.table, &mut params)?;
     }
 
-    sql.push_str(" RETURNING *");
-
-    tracing::debug!("执行 SQL: {}", sql);
+    Ok((catalog, params))
+}
 
-    // 构建查询
-    let mut query = sqlx::query(&sql);
-    
-    // 绑定 SET 值
-    for value in obj.values() {
-        query = bind_json_value(query, value);
-    }
-    
-    // 绑定 WHERE 值
-    for value in conditions.values() {
-        query = query.bind(value);
+/// 依次执行语句并合并返回的行
+async fn fetch_statements(
+    tx: &mut Transaction<'_, Postgres>,
+    statements: Vec<(String, PgArguments)>,
+) -> Result<Value, AppError> {
+    let mut rows = Vec::new();
+    for (sql, args) in statements {
+        tracing::debug!("执行 SQL: {}", sql);
+        rows.extend(sqlx::query_with(&sql, args).fetch_all(&mut **tx).await?);
     }
+    Ok(rows_to_json(&rows))
+}
 
-    let rows = query.fetch_all(&mut **tx).await?;
-    rows_to_json(rows)
+/// 执行查询操作，结果与写操作的 RETURNING 结果一样可以被后续操作引用
+async fn execute_select(
+    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
+    op: &TransactionOperation,
+) -> Result<Value, AppError> {
+    let (_, params) = operation_params(pool, op).await?;
+    let builder = SqlBuilder::new(op.schema.clone(), op.table.clone(), params)?;
+    fetch_statements(tx, vec![builder.build_select()?]).await
 }
 
-/// 执行删除操作
-async fn execute_delete(
+/// 执行插入操作，data 可以是对象或对象数组
+async fn execute_insert(
+    pool: &PgPool,
     tx: &mut Transaction<'_, Postgres>,
     op: &TransactionOperation,
 ) -> Result<Value, AppError> {
-    let conditions = op.conditions.as_ref().ok_or_else(|| {
-        AppError::InvalidQuery("DELETE 操作需要提供 where 条件".to_string())
-    })?;
+    let data = op
+        .data
+        .as_ref()
+        .ok_or_else(|| AppError::InvalidQuery("POST 操作需要提供 data 字段".to_string()))?;
 
-    // 验证标识符
-    QueryParams::sanitize_identifier(&op.schema)?;
-    QueryParams::sanitize_identifier(&op.table)?;
+    let (_, params) = operation_params(pool, op).await?;
+    let builder = SqlBuilder::new(op.schema.clone(), op.table.clone(), params)?;
+    fetch_statements(tx, builder.build_insert(data)?).await
+}
 
-    // 构建 DELETE SQL
-    let mut sql = format!("DELETE FROM \"{}\".\"{}\"", op.schema, op.table);
+/// 执行 upsert 操作（INSERT ... ON CONFLICT），data 可以是对象或对象数组
+async fn execute_upsert(
+    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
+    op: &TransactionOperation,
+) -> Result<Value, AppError> {
+    let data = op
+        .data
+        .as_ref()
+        .ok_or_else(|| AppError::InvalidQuery("UPSERT 操作需要提供 data 字段".to_string()))?;
 
-    let mut where_clauses = Vec::new();
-    let mut param_index = 1;
+    let (catalog, mut params) = operation_params(pool, op).await?;
+    params.on_conflict = op
+        .on_conflict
+        .as_ref()
+        .map(|columns| {
+            columns
+                .iter()
+                .map(|column| QueryParams::sanitize_identifier(column))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    prepare_upsert(&catalog, &op.schema, &op.table, &mut params)?;
+
+    let builder = SqlBuilder::new(op.schema.clone(), op.table.clone(), params)?;
+    let resolution = op.resolution.unwrap_or(Resolution::MergeDuplicates);
+    fetch_statements(tx, builder.build_upsert(data, resolution)?).await
+}
 
-    for (key, _) in conditions.iter() {
-        QueryParams::sanitize_identifier(key)?;
-        where_clauses.push(format!("\"{}\" = ${}", key, param_index));
-        param_index += 1;
-    }
+/// 执行更新操作
+async fn execute_update(
+    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
+    op: &TransactionOperation,
+) -> Result<Value, AppError> {
+    let data = op
+        .data
+        .as_ref()
+        .ok_or_else(|| AppError::InvalidQuery("PATCH 操作需要提供 data 字段".to_string()))?;
 
-    if where_clauses.is_empty() {
+    if op.conditions.as_ref().is_none_or(HashMap::is_empty) {
         return Err(AppError::InvalidQuery(
-            "DELETE 操作必须提供 WHERE 条件，以防止误删除全表".to_string(),
+            "PATCH 操作需要提供 where 条件".to_string(),
         ));
     }
 
-    sql.push_str(&format!(" WHERE {}", where_clauses.join(" AND ")));
-    sql.push_str(" RETURNING *");
-
-    tracing::debug!("执行 SQL: {}", sql);
-
-    // 构建查询
-    let mut query = sqlx::query(&sql);
-    for value in conditions.values() {
-        query = query.bind(value);
-    }
-
-    let rows = query.fetch_all(&mut **tx).await?;
-    rows_to_json(rows)
-}
-
-/// 绑定 JSON 值到 SQL 查询
-fn bind_json_value<'q>(
-    query: sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments>,
-    value: &'q Value,
-) -> sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments> {
-    match value {
-        Value::Null => query.bind(None::<String>),
-        Value::Bool(b) => query.bind(*b),
-        Value::Number(n) => {
-            if let Some(i) = n.as_i64() {
-                query.bind(i)
-            } else if let Some(f) = n.as_f64() {
-                query.bind(f)
-            } else {
-                query.bind(n.to_string())
-            }
-        }
-        Value::String(s) => query.bind(s.as_str()),
-        Value::Array(_) | Value::Object(_) => query.bind(value.to_string()),
-    }
+    let (_, params) = operation_params(pool, op).await?;
+    let builder = SqlBuilder::new(op.schema.clone(), op.table.clone(), params)?;
+    fetch_statements(tx, vec![builder.build_update(data)?]).await
 }
 
-/// 将数据库行转换为 JSON
-fn rows_to_json(rows: Vec<sqlx::postgres::PgRow>) -> Result<Value, AppError> {
-    let mut result = Vec::new();
-
-    for row in rows {
-        let mut obj = serde_json::Map::new();
-
-        for column in row.columns() {
-            let key = column.name().to_string();
-            let idx = column.ordinal();
-
-            // 尝试获取不同类型的值
-            let value: Value = if let Ok(v) = row.try_get::<String, _>(idx) {
-                Value::String(v)
-            } else if let Ok(v) = row.try_get::<i32, _>(idx) {
-                serde_json::json!(v)
-            } else if let Ok(v) = row.try_get::<i64, _>(idx) {
-                serde_json::json!(v)
-            } else if let Ok(v) = row.try_get::<f64, _>(idx) {
-                serde_json::json!(v)
-            } else if let Ok(v) = row.try_get::<bool, _>(idx) {
-                Value::Bool(v)
-            } else if let Ok(v) = row.try_get::<Option<String>, _>(idx) {
-                v.map(Value::String).unwrap_or(Value::Null)
-            } else if let Ok(v) = row.try_get::<Option<i32>, _>(idx) {
-                v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-            } else if let Ok(v) = row.try_get::<Option<i64>, _>(idx) {
-                v.map(|n| serde_json::json!(n)).unwrap_or(Value::Null)
-            } else if let Ok(v) = row.try_get::<serde_json::Value, _>(idx) {
-                v
-            } else {
-                Value::Null
-            };
-
-            obj.insert(key, value);
-        }
-
-        result.push(Value::Object(obj));
+/// 执行删除操作
+async fn execute_delete(
+    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
+    op: &TransactionOperation,
+) -> Result<Value, AppError> {
+    if op.conditions.as_ref().is_none_or(HashMap::is_empty) {
+        return Err(AppError::InvalidQuery(
+            "DELETE 操作必须提供 WHERE 条件，以防止误删除全表".to_string(),
+        ));
     }
 
-    Ok(Value::Array(result))
+    let (_, params) = operation_params(pool, op).await?;
+    let builder = SqlBuilder::new(op.schema.clone(), op.table.clone(), params)?;
+    fetch_statements(tx, vec![builder.build_delete()?]).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_transaction_request_deserialization() {
@@ -389,5 +719,125 @@ mod tests {
 
         let req: TransactionRequest = serde_json::from_str(json).unwrap();
         assert_eq!(req.operations.len(), 2);
+        assert_eq!(req.on_error, OnError::RollbackAll);
+
+        let req: TransactionRequest =
+            serde_json::from_value(json!({"operations": [], "on_error": "skip"})).unwrap();
+        assert_eq!(req.on_error, OnError::Skip);
+        assert!(serde_json::from_value::<TransactionRequest>(
+            json!({"operations": [], "on_error": "ignore"})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_transaction_options() {
+        let req: TransactionRequest = serde_json::from_value(json!({"operations": []})).unwrap();
+        assert_eq!(req.options.set_transaction_sql(), None);
+        assert_eq!(req.max_retries, DEFAULT_MAX_RETRIES);
+
+        let req: TransactionRequest = serde_json::from_value(json!({
+            "operations": [],
+            "isolation": "serializable",
+            "read_only": true,
+            "deferrable": true,
+            "max_retries": 5
+        }))
+        .unwrap();
+        assert_eq!(
+            req.options.set_transaction_sql().as_deref(),
+            Some("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE")
+        );
+        assert_eq!(req.max_retries, 5);
+
+        assert!(serde_json::from_value::<TransactionRequest>(
+            json!({"operations": [], "isolation": "snapshot"})
+        )
+        .is_err());
+
+        assert!(retry_delay(1) >= std::time::Duration::from_millis(20));
+        assert!(retry_delay(30) <= std::time::Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_upsert_operation_deserialization() {
+        let json = r#"{
+            "operations": [
+                {
+                    "method": "UPSERT",
+                    "schema": "public",
+                    "table": "stock",
+                    "on_conflict": ["sku", "warehouse"],
+                    "resolution": "ignore-duplicates",
+                    "data": [{"sku": "a", "warehouse": "w1", "qty": 5}]
+                }
+            ]
+        }"#;
+
+        let req: TransactionRequest = serde_json::from_str(json).unwrap();
+        let op = &req.operations[0];
+        assert!(matches!(op.method, OperationType::Upsert));
+        assert_eq!(op.resolution, Some(Resolution::IgnoreDuplicates));
+        assert_eq!(op.on_conflict.as_deref().map(<[String]>::len), Some(2));
+    }
+
+    #[test]
+    fn test_get_operation_deserialization() {
+        let op: TransactionOperation = serde_json::from_value(json!({
+            "method": "GET",
+            "schema": "public",
+            "table": "stock",
+            "where": {"sku": "A-1", "qty.gte": 5, "or": "(warehouse.eq.w1,warehouse.eq.w2)"},
+            "select": "sku,qty",
+            "order": "qty.desc",
+            "limit": 1
+        }))
+        .unwrap();
+        assert!(matches!(op.method, OperationType::Get));
+        assert_eq!(op.conditions.as_ref().map(HashMap::len), Some(3));
+        assert_eq!(op.limit, Some(1));
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let results = vec![
+            json!([{"id": 7, "status": "new"}]),
+            json!([{"sku": "a"}, {"sku": "b"}]),
+            Value::Null,
+        ];
+        let names = HashMap::from([("new_order".to_string(), 0)]);
+        let refs = References {
+            results: &results,
+            names: &names,
+        };
+
+        // 只替换顶层列值，JSON 列内部的 {"$ref": ...} 原样保留
+        let data = json!({
+            "order_id": {"$ref": "new_order.id"},
+            "items": [{"sku": {"$ref": "1.1.sku"}}],
+            "meta": {"$ref": "0.status", "note": "x"}
+        });
+        assert_eq!(
+            refs.resolve(&data).unwrap(),
+            json!({
+                "order_id": 7,
+                "items": [{"sku": {"$ref": "1.1.sku"}}],
+                "meta": {"$ref": "0.status", "note": "x"}
+            })
+        );
+
+        let rows = json!([{"sku": {"$ref": "1.0.sku"}}, {"sku": {"$ref": "1.1.sku"}}]);
+        assert_eq!(refs.resolve(&rows).unwrap(), json!([{"sku": "a"}, {"sku": "b"}]));
+
+        for dangling in ["2.id", "3.id", "missing.id", "0.total", "1.sku", "1.5.sku", "0"] {
+            assert!(refs.lookup(dangling).is_err(), "{}", dangling);
+        }
+
+        let ops: Vec<TransactionOperation> = serde_json::from_value(json!([
+            {"name": "a", "method": "POST", "schema": "public", "table": "t"},
+            {"name": "a", "method": "POST", "schema": "public", "table": "t"}
+        ]))
+        .unwrap();
+        assert!(operation_names(&ops).is_err());
     }
 }